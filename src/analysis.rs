@@ -1,28 +1,109 @@
 //! This module contains the core analysis pipeline logic.
 
 use crate::{
-    cumulative_price_change, find_tickers, klines,
-    storage_utils::{AppConfig, AsyncStorageManager},
+    cumulative_price_change, find_tickers, klines, market_data,
+    progress::{ProgressEvent, ProgressReporter, Stage},
+    storage::{self, RunMetadata, RunOutcome, Storage},
+    storage_utils,
 };
 use anyhow::Result;
+use std::fmt;
+use tokio_util::sync::CancellationToken;
+
+/// Returned by every pipeline stage that stopped because its token was
+/// cancelled. Nothing is saved after a cancellation, so the previous results
+/// stay in place.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Refresh cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
 
 /// Runs the full analysis pipeline:
-/// 1. Fetches exchange info to get tradable symbols.
+/// 1. Fetches exchange info from every configured market.
 /// 2. Fetches the kline (candlestick) data for each symbol.
 /// 3. Analyzes the klines to calculate cumulative price changes.
-pub async fn run_analysis_pipeline() -> Result<()> {
+///
+/// Each stage and every downloaded symbol is reported on `progress`. Cancelling
+/// `cancel` stops the pipeline with a [`Cancelled`] error. Every run, finished
+/// or not, is recorded in storage.
+pub async fn run_analysis_pipeline(
+    progress: &ProgressReporter,
+    cancel: &CancellationToken,
+) -> Result<()> {
     // Load application configuration
-    let storage = AsyncStorageManager::new_relative("storage").await?;
-    let app_config: AppConfig = storage.load("config").await?;
+    let app_config = storage_utils::load_config().await?;
+    let storage = storage::open(&app_config).await?;
+
+    let started_at = chrono::Utc::now().timestamp_millis();
+    let mut summary = klines::FetchSummary::default();
+    let result = run_stages(
+        &app_config,
+        storage.as_ref(),
+        &mut summary,
+        progress,
+        cancel,
+    )
+    .await;
+
+    let outcome = match &result {
+        Ok(()) => RunOutcome::Completed,
+        Err(e) if e.is::<Cancelled>() => RunOutcome::Cancelled,
+        Err(_) => RunOutcome::Failed,
+    };
+    let recorded = storage
+        .record_run(&RunMetadata {
+            started_at,
+            finished_at: chrono::Utc::now().timestamp_millis(),
+            outcome,
+            symbols: summary.fetched,
+            failures: summary.failed,
+        })
+        .await;
+    result.and(recorded)
+}
+
+async fn run_stages(
+    app_config: &storage_utils::AppConfig,
+    storage: &dyn Storage,
+    summary: &mut klines::FetchSummary,
+    progress: &ProgressReporter,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let sources = market_data::from_config(app_config)?;
 
     // Step 1: Fetch Metadata
-    find_tickers::fetch_exchange_info(&app_config.filters).await?;
+    progress.send(ProgressEvent::Stage(Stage::ExchangeInfo));
+    for source in &sources {
+        let filters = app_config.filters_for(source.exchange(), source.market());
+        find_tickers::fetch_exchange_info(
+            storage,
+            source.as_ref(),
+            filters,
+            &app_config.retry,
+            cancel,
+        )
+        .await?;
+    }
 
     // Step 2: Download Candles
-    klines::run(&app_config.klines, &app_config.filters).await?;
+    progress.send(ProgressEvent::Stage(Stage::Klines));
+    *summary = klines::run(storage, &sources, app_config, progress, cancel).await?;
 
     // Step 3: Analyze Data
-    cumulative_price_change::run(app_config.rsi_period).await?;
+    progress.send(ProgressEvent::Stage(Stage::Analysis));
+    cumulative_price_change::run(
+        storage,
+        &app_config.indicator_registry()?,
+        &app_config.snapshots,
+        cancel,
+    )
+    .await?;
 
     Ok(())
 }
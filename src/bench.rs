@@ -0,0 +1,124 @@
+//! Times the serial and the parallel analysis paths on synthetic klines, so
+//! the worker pool can be checked against the single-threaded loop on the
+//! machine that runs the screener.
+
+use crate::cumulative_price_change::{self, ResultItem};
+use crate::indicators::{IndicatorConfig, IndicatorRegistry};
+use crate::klines::{Kline, KlineResult};
+use crate::storage_utils::{Exchange, Market};
+use anyhow::{Result, bail};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::{Duration, Instant};
+
+const INTERVAL_MS: i64 = 60_000;
+
+pub struct BenchOptions {
+    pub symbols: usize,
+    pub candles: usize,
+    pub rounds: usize,
+    pub rsi_period: u32,
+}
+
+/// A random walk per symbol, seeded so every run analyzes the same data.
+fn synthetic_series(symbols: usize, candles: usize) -> Vec<KlineResult> {
+    let mut rng = StdRng::seed_from_u64(42);
+    (0..symbols)
+        .map(|i| {
+            let mut price = rng.gen_range(0.01..50_000.0);
+            let klines = (0..candles as i64)
+                .map(|t| {
+                    let open = price;
+                    price *= 1.0 + rng.gen_range(-0.01..0.01);
+                    let volume = rng.gen_range(1.0..1_000.0);
+                    Kline {
+                        open_time: t * INTERVAL_MS,
+                        open,
+                        high: open.max(price) * 1.002,
+                        low: open.min(price) * 0.998,
+                        close: price,
+                        volume,
+                        close_time: (t + 1) * INTERVAL_MS - 1,
+                        quote_volume: volume * price,
+                        trades: Some(rng.gen_range(1..500)),
+                        taker_buy_base_volume: Some(volume / 2.0),
+                        taker_buy_quote_volume: Some(volume * price / 2.0),
+                    }
+                })
+                .collect();
+            KlineResult {
+                symbol: format!("SYM{}USDT", i),
+                exchange: Exchange::Binance,
+                market: Market::UsdM,
+                interval: "1m".to_string(),
+                underlying_sub_type: Vec::new(),
+                klines,
+            }
+        })
+        .collect()
+}
+
+fn median(mut times: Vec<Duration>) -> Duration {
+    times.sort_unstable();
+    times[times.len() / 2]
+}
+
+/// Runs `analyze` on a fresh copy of the data every round, timing only the
+/// analysis. Returns the median time and the last round's results.
+fn time_path(
+    data: &[KlineResult],
+    rounds: usize,
+    analyze: impl Fn(Vec<KlineResult>) -> Vec<(ResultItem, i64)>,
+) -> (Duration, Vec<(ResultItem, i64)>) {
+    let mut times = Vec::with_capacity(rounds);
+    let mut results = Vec::new();
+    for _ in 0..rounds {
+        let input = data.to_vec();
+        let started = Instant::now();
+        results = analyze(input);
+        times.push(started.elapsed());
+    }
+    (median(times), results)
+}
+
+pub fn run(options: &BenchOptions) -> Result<()> {
+    if options.rounds == 0 {
+        bail!("At least one round is needed");
+    }
+    let data = synthetic_series(options.symbols, options.candles);
+    let registry = IndicatorRegistry::from_config(&[IndicatorConfig::Rsi {
+        period: options.rsi_period,
+    }])?;
+
+    let (serial, serial_results) = time_path(&data, options.rounds, |series| {
+        cumulative_price_change::analyze_serial(series.into_iter(), &registry)
+    });
+    let (parallel, parallel_results) = time_path(&data, options.rounds, |series| {
+        cumulative_price_change::analyze_parallel(series.into_iter(), &registry)
+    });
+
+    if serial_results != parallel_results {
+        bail!("The parallel path returned different results than the serial one");
+    }
+
+    println!(
+        "{} symbols x {} candles, median of {} rounds on {} threads",
+        options.symbols,
+        options.candles,
+        options.rounds,
+        rayon::current_num_threads()
+    );
+    for (name, time) in [("serial", serial), ("parallel", parallel)] {
+        println!(
+            "{:<9} {:>10.2} ms {:>12.0} symbols/s",
+            name,
+            time.as_secs_f64() * 1000.0,
+            options.symbols as f64 / time.as_secs_f64()
+        );
+    }
+    println!(
+        "speedup   {:>10.2}x",
+        serial.as_secs_f64() / parallel.as_secs_f64()
+    );
+    Ok(())
+}
@@ -0,0 +1,288 @@
+//! Binance implementation of [`MarketDataSource`], covering the USDT-M futures
+//! (`/fapi`), COIN-M futures (`/dapi`) and Spot (`/api/v3`) markets.
+
+use crate::find_tickers::ExchangeInfo;
+use crate::klines::Kline;
+use crate::market_data::{CandleQuery, FetchError, MarketDataSource};
+use crate::rate_limit::RateLimiter;
+use crate::storage_utils::{Exchange, Market};
+use anyhow::Result;
+use async_trait::async_trait;
+use regex::Regex;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, Response, StatusCode};
+use serde_json::{Map, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Binance counts request weight per IP in one minute windows.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+pub struct Binance {
+    market: Market,
+    client: Client,
+    base_url: String,
+    limiter: RateLimiter,
+}
+
+impl Binance {
+    pub fn new(market: Market) -> Result<Self> {
+        let base_url = match market {
+            Market::UsdM => "https://fapi.binance.com",
+            Market::CoinM => "https://dapi.binance.com",
+            Market::Spot => "https://api.binance.com",
+        };
+        Self::with_base_url(market, base_url)
+    }
+
+    /// Points the source at another host, e.g. a local stand-in for the API.
+    pub fn with_base_url(market: Market, base_url: &str) -> Result<Self> {
+        let client = Client::builder().pool_max_idle_per_host(50).build()?;
+        Ok(Self {
+            market,
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            limiter: RateLimiter::new(default_weight_limit(market), RATE_WINDOW),
+        })
+    }
+
+    /// Feeds the weight Binance reports as used this minute into the limiter.
+    fn observe_headers(&self, headers: &HeaderMap) {
+        if let Some(used) = headers
+            .get("x-mbx-used-weight-1m")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u32>().ok())
+        {
+            self.limiter.observe_used_weight(used);
+        }
+    }
+
+    /// Pauses the limiter after a 418 or 429, for as long as Binance asks.
+    async fn back_off(&self, response: Response) -> FetchError {
+        let status = response.status();
+        let retry_after = retry_after(response.headers());
+        let text = response.text().await.unwrap_or_default();
+        let wait = retry_after
+            .or_else(|| ban_remaining(&text))
+            .unwrap_or(RATE_WINDOW);
+        self.limiter.pause(wait);
+
+        let until = chrono::Utc::now().timestamp_millis() + wait.as_millis() as i64;
+        if status == StatusCode::IM_A_TEAPOT {
+            FetchError::Banned { until }
+        } else {
+            FetchError::RateLimited { until }
+        }
+    }
+
+    fn api_prefix(&self) -> &'static str {
+        match self.market {
+            Market::UsdM => "/fapi/v1",
+            Market::CoinM => "/dapi/v1",
+            Market::Spot => "/api/v3",
+        }
+    }
+}
+
+/// WebSocket host serving the market's kline streams.
+pub fn stream_base_url(market: Market) -> &'static str {
+    match market {
+        Market::UsdM => "wss://fstream.binance.com",
+        Market::CoinM => "wss://dstream.binance.com",
+        Market::Spot => "wss://stream.binance.com:9443",
+    }
+}
+
+// EXCHANGE INFO PARSING
+
+/// Brings a raw symbol entry into the shape the rest of the pipeline expects:
+/// a `status` string and an `underlyingSubType` array.
+fn normalize_symbol(market: Market, symbol: &mut Map<String, Value>) {
+    match market {
+        Market::UsdM => {}
+        // Delivery contracts report their state as `contractStatus`.
+        Market::CoinM => {
+            if !symbol.contains_key("status")
+                && let Some(status) = symbol.get("contractStatus").cloned()
+            {
+                symbol.insert("status".to_string(), status);
+            }
+        }
+        // Spot pairs have no sub types, and nest permissions in `permissionSets`.
+        Market::Spot => {
+            if !symbol.contains_key("permissions")
+                && let Some(Value::Array(sets)) = symbol.get("permissionSets")
+            {
+                let flattened: Vec<Value> = sets
+                    .iter()
+                    .filter_map(|set| set.as_array())
+                    .flatten()
+                    .cloned()
+                    .collect();
+                symbol.insert("permissions".to_string(), Value::Array(flattened));
+            }
+        }
+    }
+
+    symbol
+        .entry("underlyingSubType")
+        .or_insert_with(|| Value::Array(Vec::new()));
+}
+
+// RATE LIMITS
+
+fn default_weight_limit(market: Market) -> u32 {
+    match market {
+        Market::UsdM | Market::CoinM => 2400,
+        Market::Spot => 6000,
+    }
+}
+
+fn exchange_info_weight(market: Market) -> u32 {
+    match market {
+        Market::UsdM | Market::CoinM => 1,
+        Market::Spot => 20,
+    }
+}
+
+/// 429 warns about exceeded limits, 418 means the IP has been banned for
+/// ignoring those warnings.
+fn is_rate_limited(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::IM_A_TEAPOT
+}
+
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// Time left on an IP ban, read from a `-1003` error such as
+/// "banned until 1700000000000", plus a few seconds of margin.
+fn ban_remaining(body: &str) -> Option<Duration> {
+    if !body.contains("-1003") {
+        return None;
+    }
+    let re = Regex::new(r"until\s+(\d+)").unwrap();
+    let ban_until = re.captures(body)?.get(1)?.as_str().parse::<u64>().ok()?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    (ban_until > now).then(|| Duration::from_millis(ban_until - now) + Duration::from_secs(5))
+}
+
+// WEIGHT TABLES
+
+/// USDT-M and COIN-M share the same kline weights.
+fn futures_kline_weight(limit: u32) -> u32 {
+    match limit {
+        0..=99 => 1,
+        100..=499 => 2,
+        500..=1000 => 5,
+        _ => 10,
+    }
+}
+
+fn spot_kline_weight(_limit: u32) -> u32 {
+    2
+}
+
+#[async_trait]
+impl MarketDataSource for Binance {
+    fn exchange(&self) -> Exchange {
+        Exchange::Binance
+    }
+
+    fn market(&self) -> Market {
+        self.market
+    }
+
+    async fn list_symbols(&self) -> Result<ExchangeInfo> {
+        self.limiter
+            .acquire(exchange_info_weight(self.market))
+            .await;
+        let response = self
+            .client
+            .get(format!(
+                "{}{}/exchangeInfo",
+                self.base_url,
+                self.api_prefix()
+            ))
+            .send()
+            .await?;
+        self.observe_headers(response.headers());
+        if is_rate_limited(response.status()) {
+            return Err(self.back_off(response).await.into());
+        }
+        let response = response.error_for_status()?;
+
+        let mut exchange_info: ExchangeInfo = response.json().await?;
+        for symbol in &mut exchange_info.symbols {
+            normalize_symbol(self.market, symbol);
+        }
+        Ok(exchange_info)
+    }
+
+    async fn fetch_candles(&self, query: &CandleQuery<'_>) -> Result<Vec<Kline>, FetchError> {
+        let url = format!("{}{}/klines", self.base_url, self.api_prefix());
+        let mut params = vec![
+            ("interval", query.interval.to_string()),
+            ("limit", query.limit.min(self.max_limit()).to_string()),
+            ("symbol", query.symbol.to_string()),
+        ];
+        if let Some(start) = query.start_time {
+            params.push(("startTime", start.to_string()));
+        }
+        if let Some(end) = query.end_time {
+            params.push(("endTime", end.to_string()));
+        }
+
+        let response = self.client.get(url).query(&params).send().await?;
+        let status = response.status();
+
+        self.observe_headers(response.headers());
+
+        if is_rate_limited(status) {
+            return Err(self.back_off(response).await);
+        }
+
+        if !status.is_success() {
+            return Err(FetchError::HttpStatus {
+                status: status.as_u16(),
+            });
+        }
+
+        // Binance's kline arrays are the layout `Kline` decodes natively.
+        Ok(response.json::<Vec<Kline>>().await?)
+    }
+
+    fn max_limit(&self) -> u32 {
+        match self.market {
+            Market::UsdM | Market::CoinM => 1500,
+            Market::Spot => 1000,
+        }
+    }
+
+    fn request_weight(&self, limit: u32) -> u32 {
+        let limit = limit.min(self.max_limit());
+        match self.market {
+            Market::UsdM | Market::CoinM => futures_kline_weight(limit),
+            Market::Spot => spot_kline_weight(limit),
+        }
+    }
+
+    fn weight_limit(&self, exchange_info: &ExchangeInfo) -> u32 {
+        exchange_info
+            .rate_limits
+            .iter()
+            .find(|r| r.limit_type == "REQUEST_WEIGHT" && r.interval == "MINUTE")
+            .map(|r| r.limit)
+            .unwrap_or(default_weight_limit(self.market))
+    }
+
+    fn rate_limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+}
@@ -0,0 +1,400 @@
+//! Bybit linear perpetual implementation of [`MarketDataSource`].
+//!
+//! Instruments and candles are translated into the Binance shapes the rest of
+//! the pipeline reads. The parsing functions take raw response bodies so they
+//! can be exercised against recorded responses without network access.
+
+use crate::find_tickers::ExchangeInfo;
+use crate::klines::Kline;
+use crate::market_data::{self, CandleQuery, FetchError, MarketDataSource};
+use crate::rate_limit::RateLimiter;
+use crate::storage_utils::{Exchange, Market};
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const BASE_URL: &str = "https://api.bybit.com";
+
+/// Bybit allows 600 requests per IP in any 5 second window.
+const REQUESTS_PER_WINDOW: u32 = 600;
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+const MAX_LIMIT: u32 = 1000;
+const RATE_LIMITED_CODE: i64 = 10006;
+
+// DATA
+
+#[derive(Deserialize)]
+struct Envelope<T> {
+    #[serde(rename = "retCode")]
+    ret_code: i64,
+    #[serde(rename = "retMsg", default)]
+    ret_msg: String,
+    result: Option<T>,
+}
+
+#[derive(Deserialize)]
+pub struct InstrumentPage {
+    pub list: Vec<Map<String, Value>>,
+    #[serde(rename = "nextPageCursor", default)]
+    pub next_page_cursor: String,
+}
+
+#[derive(Deserialize)]
+struct KlinePage {
+    list: Vec<Vec<String>>,
+}
+
+/// Checks the envelope before decoding its result, which Bybit leaves as an
+/// empty object on errors.
+fn parse_result<T: DeserializeOwned>(body: &str) -> Result<T> {
+    let envelope = serde_json::from_str::<Envelope<Value>>(body)?;
+    if envelope.ret_code != 0 {
+        bail!("Bybit error {}: {}", envelope.ret_code, envelope.ret_msg);
+    }
+    let result = envelope
+        .result
+        .ok_or_else(|| anyhow!("Bybit response has no result"))?;
+    Ok(serde_json::from_value(result)?)
+}
+
+// PARSING
+
+/// Parses one page of `/v5/market/instruments-info` into normalized symbols.
+/// An empty `next_page_cursor` marks the last page.
+pub fn parse_instrument_page(body: &str) -> Result<InstrumentPage> {
+    let mut page: InstrumentPage = parse_result(body)?;
+    page.list = page.list.into_iter().map(normalize_instrument).collect();
+    Ok(page)
+}
+
+/// Maps Bybit's instrument fields onto the Binance names the filters use.
+/// The raw fields are kept, except `status` and `contractType` which are
+/// replaced by their Binance equivalents.
+fn normalize_instrument(mut instrument: Map<String, Value>) -> Map<String, Value> {
+    let status = match instrument.get("status").and_then(Value::as_str) {
+        Some("Trading") => "TRADING".to_string(),
+        Some(other) => other.to_uppercase(),
+        None => String::new(),
+    };
+    let contract_type = match instrument.get("contractType").and_then(Value::as_str) {
+        Some("LinearPerpetual") | Some("InversePerpetual") => "PERPETUAL",
+        Some("LinearFutures") | Some("InverseFutures") => "DELIVERY",
+        _ => "",
+    };
+
+    instrument.insert("status".to_string(), Value::from(status));
+    instrument.insert("contractType".to_string(), Value::from(contract_type));
+    for (from, to) in [("baseCoin", "baseAsset"), ("quoteCoin", "quoteAsset")] {
+        if let Some(value) = instrument.get(from).cloned() {
+            instrument.insert(to.to_string(), value);
+        }
+    }
+    instrument
+        .entry("underlyingSubType")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    instrument
+}
+
+/// Parses `/v5/market/kline` into candles, oldest first.
+pub fn parse_klines(body: &str, interval_ms: i64) -> Result<Vec<Kline>> {
+    let page: KlinePage = parse_result(body)?;
+
+    // Bybit lists candles newest first as
+    // [startTime, open, high, low, close, volume, turnover].
+    page.list
+        .into_iter()
+        .rev()
+        .map(|row| {
+            let [start, open, high, low, close, volume, turnover] = <[String; 7]>::try_from(row)
+                .map_err(|row| anyhow!("Unexpected Bybit kline row: {:?}", row))?;
+            let open_time: i64 = start.parse()?;
+            Ok(Kline {
+                open_time,
+                open: open.parse()?,
+                high: high.parse()?,
+                low: low.parse()?,
+                close: close.parse()?,
+                volume: volume.parse()?,
+                close_time: open_time + interval_ms - 1,
+                quote_volume: turnover.parse()?,
+                trades: None,
+                taker_buy_base_volume: None,
+                taker_buy_quote_volume: None,
+            })
+        })
+        .collect()
+}
+
+/// Converts a Binance style interval into Bybit's notation (`1`, `60`, `D`, ...).
+fn bybit_interval(interval: &str) -> Option<String> {
+    match interval {
+        "1d" => Some("D".to_string()),
+        "1w" => Some("W".to_string()),
+        "1M" => Some("M".to_string()),
+        _ => {
+            let ms = market_data::interval_millis(interval)?;
+            (ms % 60_000 == 0 && ms < 86_400_000).then(|| (ms / 60_000).to_string())
+        }
+    }
+}
+
+// SOURCE
+
+pub struct Bybit {
+    client: Client,
+    base_url: String,
+    limiter: RateLimiter,
+}
+
+impl Bybit {
+    pub fn new() -> Result<Self> {
+        Self::with_base_url(BASE_URL)
+    }
+
+    /// Points the source at another host, e.g. a local stand-in for the API.
+    pub fn with_base_url(base_url: &str) -> Result<Self> {
+        let client = Client::builder().pool_max_idle_per_host(50).build()?;
+        Ok(Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            limiter: RateLimiter::new(REQUESTS_PER_WINDOW, RATE_WINDOW),
+        })
+    }
+
+    /// Holds back requests after an IP rate limit, until Bybit's reset
+    /// timestamp when it sends one.
+    fn back_off(&self, reset_at_ms: Option<u64>) -> FetchError {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let wait = match reset_at_ms {
+            Some(reset) if reset > now => Duration::from_millis(reset - now),
+            _ => RATE_WINDOW,
+        };
+        self.limiter.pause(wait);
+        FetchError::RateLimited {
+            until: (now + wait.as_millis() as u64) as i64,
+        }
+    }
+}
+
+#[async_trait]
+impl MarketDataSource for Bybit {
+    fn exchange(&self) -> Exchange {
+        Exchange::Bybit
+    }
+
+    fn market(&self) -> Market {
+        Market::UsdM
+    }
+
+    async fn list_symbols(&self) -> Result<ExchangeInfo> {
+        let url = format!("{}/v5/market/instruments-info", self.base_url);
+        let mut symbols = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            self.limiter.acquire(1).await;
+            let mut query = vec![
+                ("category", "linear".to_string()),
+                ("limit", "1000".to_string()),
+            ];
+            if let Some(c) = &cursor {
+                query.push(("cursor", c.clone()));
+            }
+
+            let body = self
+                .client
+                .get(&url)
+                .query(&query)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+
+            let page = parse_instrument_page(&body)?;
+            symbols.extend(page.list);
+            if page.next_page_cursor.is_empty() {
+                break;
+            }
+            cursor = Some(page.next_page_cursor);
+        }
+
+        Ok(ExchangeInfo {
+            symbols,
+            rate_limits: Vec::new(),
+        })
+    }
+
+    async fn fetch_candles(&self, query: &CandleQuery<'_>) -> Result<Vec<Kline>, FetchError> {
+        let unsupported = || FetchError::InvalidRequest {
+            message: format!("Bybit does not support the {} interval", query.interval),
+        };
+        let interval_ms = market_data::interval_millis(query.interval).ok_or_else(unsupported)?;
+        let mut params = vec![
+            ("category", "linear".to_string()),
+            ("symbol", query.symbol.to_string()),
+            (
+                "interval",
+                bybit_interval(query.interval).ok_or_else(unsupported)?,
+            ),
+            ("limit", query.limit.min(MAX_LIMIT).to_string()),
+        ];
+        if let Some(start) = query.start_time {
+            params.push(("start", start.to_string()));
+        }
+        if let Some(end) = query.end_time {
+            params.push(("end", end.to_string()));
+        }
+
+        let response = self
+            .client
+            .get(format!("{}/v5/market/kline", self.base_url))
+            .query(&params)
+            .send()
+            .await?;
+        let status = response.status();
+        let reset_at = response
+            .headers()
+            .get("X-Bapi-Limit-Reset-Timestamp")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+
+        if status == StatusCode::FORBIDDEN || status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(self.back_off(reset_at));
+        }
+        if !status.is_success() {
+            return Err(FetchError::HttpStatus {
+                status: status.as_u16(),
+            });
+        }
+
+        let body = response.text().await?;
+        let envelope = serde_json::from_str::<Envelope<Value>>(&body)?;
+        if envelope.ret_code == RATE_LIMITED_CODE {
+            return Err(self.back_off(reset_at));
+        }
+        if envelope.ret_code != 0 {
+            return Err(FetchError::Api {
+                code: envelope.ret_code.to_string(),
+                message: envelope.ret_msg,
+            });
+        }
+
+        parse_klines(&body, interval_ms).map_err(|e| FetchError::Decode {
+            message: e.to_string(),
+        })
+    }
+
+    fn max_limit(&self) -> u32 {
+        MAX_LIMIT
+    }
+
+    fn request_weight(&self, _limit: u32) -> u32 {
+        1
+    }
+
+    fn weight_limit(&self, _exchange_info: &ExchangeInfo) -> u32 {
+        REQUESTS_PER_WINDOW
+    }
+
+    fn rate_limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::find_tickers::matches_filters;
+    use crate::storage_utils::{AppConfig, DEFAULT_CONFIG};
+
+    const INSTRUMENTS_PAGE_1: &str = include_str!("../tests/fixtures/bybit/instruments_page1.json");
+    const INSTRUMENTS_PAGE_2: &str = include_str!("../tests/fixtures/bybit/instruments_page2.json");
+    const KLINE: &str = include_str!("../tests/fixtures/bybit/kline.json");
+    const ERROR: &str = include_str!("../tests/fixtures/bybit/error.json");
+
+    fn field<'a>(instrument: &'a Map<String, Value>, key: &str) -> &'a str {
+        instrument[key].as_str().unwrap()
+    }
+
+    #[test]
+    fn instruments_are_normalized() {
+        let page = parse_instrument_page(INSTRUMENTS_PAGE_1).unwrap();
+        let [perpetual, futures] = &page.list[..] else {
+            panic!("expected two instruments");
+        };
+
+        assert_eq!(field(perpetual, "symbol"), "BTCUSDT");
+        assert_eq!(field(perpetual, "status"), "TRADING");
+        assert_eq!(field(perpetual, "contractType"), "PERPETUAL");
+        assert_eq!(field(perpetual, "baseAsset"), "BTC");
+        assert_eq!(field(perpetual, "quoteAsset"), "USDT");
+        assert!(!perpetual.contains_key("underlyingType"));
+        assert_eq!(perpetual["underlyingSubType"], Value::Array(Vec::new()));
+        assert_eq!(field(futures, "contractType"), "DELIVERY");
+        assert!(!page.next_page_cursor.is_empty());
+
+        let last = parse_instrument_page(INSTRUMENTS_PAGE_2).unwrap();
+        assert_eq!(field(&last.list[0], "status"), "PRELAUNCH");
+        assert!(last.next_page_cursor.is_empty());
+    }
+
+    #[test]
+    fn default_filters_keep_trading_perpetuals() {
+        let config: AppConfig = serde_json::from_str(DEFAULT_CONFIG).unwrap();
+        let filters = config.filters_for(Exchange::Bybit, Market::UsdM);
+        let mut kept: Vec<String> = Vec::new();
+        for body in [INSTRUMENTS_PAGE_1, INSTRUMENTS_PAGE_2] {
+            for instrument in parse_instrument_page(body).unwrap().list {
+                if matches_filters(&instrument, filters) {
+                    kept.push(field(&instrument, "symbol").to_string());
+                }
+            }
+        }
+        assert_eq!(kept, ["BTCUSDT"]);
+    }
+
+    #[test]
+    fn klines_are_oldest_first_with_derived_close_time() {
+        let klines = parse_klines(KLINE, 60_000).unwrap();
+
+        let open_times: Vec<i64> = klines.iter().map(|k| k.open_time).collect();
+        assert_eq!(open_times, [1670608800000, 1670608860000, 1670608920000]);
+        let first = &klines[0];
+        assert_eq!(first.close_time, 1670608859999);
+        assert_eq!(first.open, 17061.0);
+        assert_eq!(first.high, 17074.5);
+        assert_eq!(first.low, 17057.0);
+        assert_eq!(first.close, 17071.0);
+        assert_eq!(first.volume, 268.611);
+        assert_eq!(first.quote_volume, 4582988.1395);
+        assert_eq!(first.trades, None);
+    }
+
+    #[test]
+    fn error_envelopes_are_reported() {
+        for error in [
+            parse_klines(ERROR, 60_000).unwrap_err(),
+            parse_instrument_page(ERROR).err().unwrap(),
+        ] {
+            assert_eq!(
+                error.to_string(),
+                "Bybit error 10001: params error: Symbol Is Invalid"
+            );
+        }
+    }
+
+    #[test]
+    fn intervals_use_bybit_notation() {
+        assert_eq!(bybit_interval("1m").as_deref(), Some("1"));
+        assert_eq!(bybit_interval("4h").as_deref(), Some("240"));
+        assert_eq!(bybit_interval("1d").as_deref(), Some("D"));
+    }
+}
@@ -0,0 +1,299 @@
+//! Command-line interface. Every subcommand wraps one pipeline entry point so
+//! the screener can run from cron or scripts; without one the TUI starts.
+//!
+//! Exit codes: 0 on success, 1 on errors, 2 on invalid usage, 3 when klines
+//! were downloaded but some symbols failed, 130 when interrupted with Ctrl-C.
+
+use crate::analysis::{self, Cancelled};
+use crate::bench::{self, BenchOptions};
+use crate::comfy_table::{self, Column, TableOptions, TableStyle};
+use crate::cumulative_price_change;
+use crate::export::{self, ExportFormat};
+use crate::find_tickers;
+use crate::klines::{self, FetchFailure};
+use crate::market_data;
+use crate::progress::ProgressReporter;
+use crate::storage;
+use crate::storage_utils;
+use crate::tui::{self, OutputData};
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tokio_util::sync::CancellationToken;
+
+const EXIT_FAILURE: u8 = 1;
+const EXIT_PARTIAL: u8 = 3;
+const EXIT_CANCELLED: u8 = 130;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Ranks crypto markets by price movement and indicators"
+)]
+pub struct Cli {
+    /// Read the configuration from this file [env: COMFY_SCREENER_CONFIG].
+    /// Defaults to `comfy-screener/config.json` in the user config directory.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Keep all data in this directory [env: COMFY_SCREENER_STORAGE_DIR].
+    /// Defaults to `comfy-screener/` in the user data directory.
+    #[arg(long, global = true, value_name = "DIR")]
+    pub storage_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Start the interactive terminal UI (default).
+    Tui,
+    /// Download the symbol lists of every configured market.
+    FetchSymbols,
+    /// Download klines for the symbols saved by `fetch-symbols`.
+    FetchKlines,
+    /// Rank the downloaded klines and save the results.
+    Analyze,
+    /// Run the full pipeline: fetch-symbols, fetch-klines and analyze.
+    Run,
+    /// Print the latest results as a table.
+    Show(ShowArgs),
+    /// Write the latest results to a timestamped file.
+    Export(ExportArgs),
+    /// Time the serial and parallel analysis on synthetic klines.
+    Bench(BenchArgs),
+}
+
+#[derive(Args)]
+pub struct ShowArgs {
+    /// Only print the first N results.
+    #[arg(long, value_name = "N")]
+    pub top: Option<usize>,
+
+    /// Columns to print, in order: rank, symbol, market, subtypes, movement
+    /// or the key of an indicator column such as rsi. Defaults to all of them.
+    #[arg(long, value_delimiter = ',', value_name = "COLUMNS")]
+    pub columns: Option<Vec<String>>,
+
+    #[arg(long, value_enum, default_value_t)]
+    pub style: TableStyle,
+
+    /// Print without colors.
+    #[arg(long)]
+    pub no_color: bool,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Defaults to `export_format` from the configuration.
+    #[arg(long, value_enum)]
+    pub format: Option<ExportFormat>,
+
+    /// Also export the raw klines.
+    #[arg(long)]
+    pub klines: bool,
+
+    /// Defaults to `exports/` in storage.
+    #[arg(long, value_name = "DIR")]
+    pub output_dir: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct BenchArgs {
+    #[arg(long, default_value_t = 500)]
+    pub symbols: usize,
+
+    /// Candles per symbol.
+    #[arg(long, default_value_t = 1500)]
+    pub candles: usize,
+
+    /// Rounds per path, the median is reported.
+    #[arg(long, default_value_t = 5)]
+    pub rounds: usize,
+
+    #[arg(long, default_value_t = 14)]
+    pub rsi_period: u32,
+}
+
+// COMMANDS
+
+async fn fetch_symbols(cancel: &CancellationToken) -> Result<()> {
+    let app_config = storage_utils::load_config().await?;
+    let storage = storage::open(&app_config).await?;
+    for source in market_data::from_config(&app_config)? {
+        let filters = app_config.filters_for(source.exchange(), source.market());
+        find_tickers::fetch_exchange_info(
+            storage.as_ref(),
+            source.as_ref(),
+            filters,
+            &app_config.retry,
+            cancel,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn fetch_klines(cancel: &CancellationToken) -> Result<()> {
+    let app_config = storage_utils::load_config().await?;
+    let storage = storage::open(&app_config).await?;
+    let sources = market_data::from_config(&app_config)?;
+    let progress = ProgressReporter::default();
+    klines::run(storage.as_ref(), &sources, &app_config, &progress, cancel).await?;
+    Ok(())
+}
+
+async fn analyze(cancel: &CancellationToken) -> Result<()> {
+    let app_config = storage_utils::load_config().await?;
+    let storage = storage::open(&app_config).await?;
+    cumulative_price_change::run(
+        storage.as_ref(),
+        &app_config.indicator_registry()?,
+        &app_config.snapshots,
+        cancel,
+    )
+    .await
+}
+
+async fn show(args: ShowArgs) -> Result<()> {
+    let registry = storage_utils::load_config().await?.indicator_registry()?;
+    let columns = match &args.columns {
+        Some(names) => Column::parse_list(names, &registry)?,
+        None => Column::all(&registry),
+    };
+    let data: OutputData = tui::load_data().await?;
+    let options = TableOptions {
+        columns,
+        top: args.top,
+        style: args.style,
+        color: !args.no_color,
+    };
+    println!(
+        "Last updated: {}",
+        tui::format_timestamp(data.last_updated_timestamp)
+    );
+    println!("{}", comfy_table::render(&data, &options));
+    Ok(())
+}
+
+async fn export(args: ExportArgs) -> Result<()> {
+    let format = match args.format {
+        Some(format) => format,
+        None => storage_utils::load_config().await?.export_format,
+    };
+    for path in export::export(format, args.klines, args.output_dir.as_deref()).await? {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+/// Symbols the last kline download could not fetch.
+async fn fetch_failures() -> Vec<FetchFailure> {
+    let Ok(app_config) = storage_utils::load_config().await else {
+        return Vec::new();
+    };
+    match storage::open(&app_config).await {
+        Ok(storage) => storage.load_fetch_errors().await.unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+async fn run_tui(notice: Option<String>) -> ExitCode {
+    match tui::run_tui(notice).await {
+        // Quitting is reported as an error to unwind the UI loop.
+        Err(e) if !e.to_string().contains("Quit") => {
+            eprintln!("TUI Error: {}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+/// A token cancelled by Ctrl-C, so headless runs stop without saving
+/// partial results.
+fn cancel_on_ctrl_c() -> CancellationToken {
+    let cancel = CancellationToken::new();
+    let on_ctrl_c = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            on_ctrl_c.cancel();
+        }
+    });
+    cancel
+}
+
+// MAIN
+
+pub async fn run(cli: Cli) -> ExitCode {
+    if let Some(dir) = cli.storage_dir {
+        storage_utils::set_storage_dir(dir);
+    }
+    if let Some(path) = cli.config {
+        storage_utils::set_config_path(path);
+    }
+
+    // The TUI shows this once it is up, printing would draw over it.
+    let config_notice = match storage_utils::ensure_config().await {
+        Ok(notice) => notice,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            return ExitCode::from(EXIT_FAILURE);
+        }
+    };
+    let command = cli.command.unwrap_or(Command::Tui);
+    if let Some(notice) = &config_notice
+        && !matches!(command, Command::Tui)
+    {
+        eprintln!("{}", notice);
+    }
+
+    let fetches_klines = matches!(command, Command::FetchKlines | Command::Run);
+    let result = match command {
+        Command::Tui => return run_tui(config_notice).await,
+        Command::FetchSymbols => fetch_symbols(&cancel_on_ctrl_c()).await,
+        Command::FetchKlines => fetch_klines(&cancel_on_ctrl_c()).await,
+        Command::Analyze => analyze(&cancel_on_ctrl_c()).await,
+        Command::Run => {
+            analysis::run_analysis_pipeline(&ProgressReporter::default(), &cancel_on_ctrl_c()).await
+        }
+        Command::Show(args) => show(args).await,
+        Command::Export(args) => export(args).await,
+        Command::Bench(args) => bench::run(&BenchOptions {
+            symbols: args.symbols,
+            candles: args.candles,
+            rounds: args.rounds,
+            rsi_period: args.rsi_period,
+        }),
+    };
+
+    match result {
+        Ok(()) if fetches_klines => {
+            let failures = fetch_failures().await;
+            if failures.is_empty() {
+                return ExitCode::SUCCESS;
+            }
+            for failure in &failures {
+                eprintln!(
+                    "{} ({} {}): {}",
+                    failure.symbol,
+                    failure.exchange.label(),
+                    failure.market.label(),
+                    failure.error
+                );
+            }
+            eprintln!("{} symbols could not be downloaded", failures.len());
+            ExitCode::from(EXIT_PARTIAL)
+        }
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) if e.is::<Cancelled>() => {
+            eprintln!("{}", e);
+            ExitCode::from(EXIT_CANCELLED)
+        }
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
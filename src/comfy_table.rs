@@ -1,2 +1,192 @@
-// This file's functionality has been moved to `src/tui.rs` which now handles
-// data loading and rendering for the TUI.
\ No newline at end of file
+//! Non-interactive rendering of the results as a terminal table, used by the
+//! `show` command.
+
+use crate::indicators::{Coloring, IndicatorColumn, IndicatorRegistry};
+use crate::tui::{AssetResult, OutputData};
+use ::comfy_table::modifiers::UTF8_ROUND_CORNERS;
+use ::comfy_table::presets::{ASCII_FULL_CONDENSED, ASCII_MARKDOWN, UTF8_FULL_CONDENSED};
+use ::comfy_table::{Cell, CellAlignment, Color, ContentArrangement, Table};
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Column {
+    Rank,
+    Symbol,
+    Market,
+    Subtypes,
+    Movement,
+    /// A value filled by one of the configured indicators.
+    Indicator(IndicatorColumn),
+}
+
+impl Column {
+    pub const BUILTIN: [(&'static str, Column); 5] = [
+        ("rank", Column::Rank),
+        ("symbol", Column::Symbol),
+        ("market", Column::Market),
+        ("subtypes", Column::Subtypes),
+        ("movement", Column::Movement),
+    ];
+
+    /// Every built-in column followed by every indicator column.
+    pub fn all(registry: &IndicatorRegistry) -> Vec<Column> {
+        Self::BUILTIN
+            .iter()
+            .map(|&(_, column)| column)
+            .chain(registry.columns().into_iter().map(Column::Indicator))
+            .collect()
+    }
+
+    /// Looks up columns by name, either a built-in one or an indicator
+    /// column key.
+    pub fn parse_list(names: &[String], registry: &IndicatorRegistry) -> Result<Vec<Column>> {
+        let indicator_columns = registry.columns();
+        names
+            .iter()
+            .map(|name| {
+                let builtin = Self::BUILTIN.iter().find(|(n, _)| n == name);
+                let indicator = indicator_columns.iter().find(|c| c.key == name);
+                match (builtin, indicator) {
+                    (Some(&(_, column)), _) => Ok(column),
+                    (None, Some(&column)) => Ok(Column::Indicator(column)),
+                    (None, None) => {
+                        let valid: Vec<&str> = Self::BUILTIN
+                            .iter()
+                            .map(|(n, _)| *n)
+                            .chain(indicator_columns.iter().map(|c| c.key))
+                            .collect();
+                        bail!(
+                            "Unknown column {}, expected one of: {}",
+                            name,
+                            valid.join(", ")
+                        )
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            Column::Rank => "Rank",
+            Column::Symbol => "Asset",
+            Column::Market => "Market",
+            Column::Subtypes => "Type",
+            Column::Movement => "Movement (%)",
+            Column::Indicator(column) => column.header,
+        }
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Column::Rank | Column::Movement | Column::Indicator(_))
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TableStyle {
+    /// Rounded box drawing characters.
+    #[default]
+    Utf8,
+    /// ASCII only, safe to paste anywhere.
+    Plain,
+    /// A Markdown table.
+    Markdown,
+}
+
+pub struct TableOptions {
+    pub columns: Vec<Column>,
+    /// Only the first `top` results, all of them when unset.
+    pub top: Option<usize>,
+    pub style: TableStyle,
+    /// Color movement and indicator cells. Ignored for the plain and Markdown styles.
+    pub color: bool,
+}
+
+fn cell(column: Column, rank: usize, asset: &AssetResult, color: bool) -> Cell {
+    let cell = match column {
+        Column::Rank => Cell::new(rank),
+        Column::Symbol => Cell::new(&asset.symbol),
+        Column::Market => Cell::new(format!(
+            "{} {}",
+            asset.exchange.label(),
+            asset.market.label()
+        )),
+        Column::Subtypes if asset.sub_type.is_empty() => Cell::new("N/A"),
+        Column::Subtypes => Cell::new(asset.sub_type.join(", ")),
+        Column::Movement => Cell::new(format!("{:.2}", asset.movement_pct)),
+        Column::Indicator(column) => match asset.indicators.get(column.key) {
+            Some(value) => Cell::new(format!("{:.*}", column.decimals, value)),
+            None => Cell::new("-"),
+        },
+    };
+    let cell = if column.is_numeric() {
+        cell.set_alignment(CellAlignment::Right)
+    } else {
+        cell
+    };
+    if !color {
+        return cell;
+    }
+
+    match column {
+        Column::Movement if asset.movement_pct > 0.0 => cell.fg(Color::Green),
+        Column::Movement if asset.movement_pct < 0.0 => cell.fg(Color::Red),
+        Column::Indicator(column) => match asset.indicators.get(column.key) {
+            Some(&value) => match color_for(column.coloring, value) {
+                Some(color) => cell.fg(color),
+                None => cell,
+            },
+            None => cell,
+        },
+        Column::Symbol => cell.fg(Color::Cyan),
+        Column::Rank | Column::Market | Column::Subtypes => cell.fg(Color::DarkGrey),
+        Column::Movement => cell,
+    }
+}
+
+fn color_for(coloring: Coloring, value: f64) -> Option<Color> {
+    match coloring {
+        Coloring::Bands { high, .. } if value >= high => Some(Color::Red),
+        Coloring::Bands { low, .. } if value <= low => Some(Color::Green),
+        Coloring::Sign if value > 0.0 => Some(Color::Green),
+        Coloring::Sign if value < 0.0 => Some(Color::Red),
+        Coloring::Highlight { above } if value >= above => Some(Color::Yellow),
+        _ => None,
+    }
+}
+
+/// Renders the results as a table with the selected columns.
+pub fn render(data: &OutputData, options: &TableOptions) -> String {
+    let mut table = Table::new();
+    match options.style {
+        TableStyle::Utf8 => {
+            table
+                .load_preset(UTF8_FULL_CONDENSED)
+                .apply_modifier(UTF8_ROUND_CORNERS);
+        }
+        TableStyle::Plain => {
+            table.load_preset(ASCII_FULL_CONDENSED);
+        }
+        TableStyle::Markdown => {
+            table.load_preset(ASCII_MARKDOWN);
+        }
+    }
+    let color = options.color && options.style == TableStyle::Utf8;
+    if !color {
+        table.force_no_tty();
+    }
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table.set_header(options.columns.iter().map(|c| c.header()));
+
+    let top = options.top.unwrap_or(data.results.len());
+    for (i, asset) in data.results.iter().take(top).enumerate() {
+        table.add_row(
+            options
+                .columns
+                .iter()
+                .map(|&column| cell(column, i + 1, asset, color)),
+        );
+    }
+    table.to_string()
+}
@@ -1,106 +1,46 @@
-use crate::storage_utils::AsyncStorageManager;
+use crate::analysis::Cancelled;
+use crate::indicators::IndicatorRegistry;
+use crate::klines::{Kline, KlineResult};
+use crate::snapshots;
+use crate::storage::Storage;
+use crate::storage_utils::{Exchange, Market, SnapshotConfig};
 use anyhow::Result;
-use serde::de::{self, Visitor};
-use serde::{Deserialize, Deserializer, Serialize};
-use std::fmt;
-
-// --- Data Structures & Custom Deserialization (Unchanged) ---
-
-#[derive(Deserialize, Debug)]
-pub struct InputKline {
-    // Made public
-    #[serde(deserialize_with = "deserialize_f64_lenient")]
-    pub open: Option<f64>,
-    #[serde(deserialize_with = "deserialize_f64_lenient")]
-    pub close: Option<f64>,
-    #[serde(rename = "closeTime")]
-    pub close_time: Option<i64>,
-}
+use rayon::iter::{ParallelBridge, ParallelIterator};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
-#[derive(Deserialize, Debug)]
-struct SymbolData {
-    symbol: String,
-    #[serde(default)]
-    klines: Vec<InputKline>,
-    #[serde(rename = "underlyingSubType", default)]
-    underlying_sub_type: Vec<String>,
-}
+/// Series read ahead of the analysis workers.
+const ANALYSIS_QUEUE: usize = 64;
 
-#[derive(Serialize, Debug)]
-struct ResultItem {
+// --- Data Structures ---
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ResultItem {
     symbol: String,
+    exchange: Exchange,
+    market: Market,
     movement_pct: f64,
     #[serde(rename = "subType")]
     sub_type: Vec<String>,
-    rsi: Option<f64>,
+    /// Indicator values by column key.
+    indicators: BTreeMap<String, f64>,
 }
 
 #[derive(Serialize, Debug)]
-struct OutputData {
+pub struct OutputData {
     last_updated_timestamp: i64,
     results: Vec<ResultItem>,
 }
 
-struct LenientF64Visitor;
-
-impl<'de> Visitor<'de> for LenientF64Visitor {
-    type Value = Option<f64>;
-
-    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        // Corrected return type
-        formatter.write_str("a float, an integer, or a string representing a number")
-    }
-
-    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
-        Ok(Some(v))
-    }
-
-    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
-        Ok(Some(v as f64))
-    }
-
-    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
-        Ok(Some(v as f64))
-    }
-
-    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
-    where
-        E: de::Error,
-    {
-        if v.trim().is_empty() {
-            Ok(None)
-        } else {
-            v.parse::<f64>().map(Some).map_err(E::custom)
-        }
-    }
-
-    fn visit_unit<E>(self) -> Result<Self::Value, E> {
-        Ok(None)
-    }
-}
-
-fn deserialize_f64_lenient<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    deserializer.deserialize_any(LenientF64Visitor)
-}
-
 // --- Domain Logic (Unchanged) ---
 
-fn analyze_klines_data(klines: &[InputKline]) -> Option<(f64, i64)> {
-    if klines.is_empty() {
-        return None;
-    }
-
-    let is_valid =
-        |k: &&InputKline| k.open.is_some() && k.close.is_some() && k.close_time.is_some();
-    let first_kline = klines.iter().find(is_valid)?;
-    let last_kline = klines.iter().rfind(is_valid)?;
-
-    let first_close = first_kline.close?;
-    let last_close = last_kline.close?;
-    let last_close_time = last_kline.close_time?;
+fn analyze_klines_data(klines: &[Kline]) -> Option<(f64, i64)> {
+    let first_close = klines.first()?.close;
+    let last_kline = klines.last()?;
+    let last_close = last_kline.close;
+    let last_close_time = last_kline.close_time;
 
     if first_close == 0.0 {
         return None;
@@ -111,55 +51,106 @@ fn analyze_klines_data(klines: &[InputKline]) -> Option<(f64, i64)> {
     Some((cumulative_return, last_close_time))
 }
 
-// --- Main Execution (Refactored) ---
+/// Computes the movement and the indicators of a single symbol, along with
+/// the close time of its latest candle.
+pub fn analyze_symbol(
+    symbol_data: &KlineResult,
+    registry: &IndicatorRegistry,
+) -> Option<(ResultItem, i64)> {
+    let (movement_pct, last_close_time) = analyze_klines_data(&symbol_data.klines)?;
+
+    Some((
+        ResultItem {
+            symbol: symbol_data.symbol.clone(),
+            exchange: symbol_data.exchange,
+            market: symbol_data.market,
+            movement_pct,
+            sub_type: symbol_data.underlying_sub_type.clone(),
+            indicators: registry.compute(&symbol_data.klines),
+        },
+        last_close_time,
+    ))
+}
 
-pub async fn run(rsi_period: u32) -> Result<()> {
-    let storage = AsyncStorageManager::new_relative("storage").await?;
-
-    let all_symbols_data: Vec<SymbolData> = match storage.load("klines").await {
-        Ok(data) => data,
-        Err(_) => {
-            // Silently return if file doesn't exist, as the TUI will show empty state.
-            return Ok(());
-        }
-    };
-
-    let mut results = Vec::with_capacity(all_symbols_data.len());
-    let mut max_close_time = 0;
-
-    for symbol_data in all_symbols_data {
-        let rsi_value = crate::indicators::calculate_rsi(&symbol_data.klines, rsi_period); // Call the moved RSI function
-
-        if let Some((movement_pct, last_close_time)) = analyze_klines_data(&symbol_data.klines) {
-            results.push(ResultItem {
-                symbol: symbol_data.symbol,
-                movement_pct,
-                sub_type: symbol_data.underlying_sub_type,
-                rsi: rsi_value, // Store calculated RSI
-            });
-
-            if last_close_time > max_close_time {
-                max_close_time = last_close_time;
-            }
-        }
+/// Analyzes every series serially, in order.
+pub fn analyze_serial(
+    series: impl Iterator<Item = KlineResult>,
+    registry: &IndicatorRegistry,
+) -> Vec<(ResultItem, i64)> {
+    series
+        .filter_map(|s| analyze_symbol(&s, registry))
+        .collect()
+}
+
+/// Analyzes the series on the rayon pool as they arrive, returning the
+/// results in the order the series came in.
+pub fn analyze_parallel(
+    series: impl Iterator<Item = KlineResult> + Send,
+    registry: &IndicatorRegistry,
+) -> Vec<(ResultItem, i64)> {
+    let mut analyzed: Vec<(usize, (ResultItem, i64))> = series
+        .enumerate()
+        .par_bridge()
+        .filter_map(|(i, s)| analyze_symbol(&s, registry).map(|result| (i, result)))
+        .collect();
+    analyzed.sort_unstable_by_key(|(i, _)| *i);
+    analyzed.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Ranks the results by movement, biggest movers first. Ties keep their
+/// order. Returns `None` when there is nothing to show.
+pub fn build_output(mut results: Vec<ResultItem>, last_updated: i64) -> Option<OutputData> {
+    if results.is_empty() {
+        return None;
     }
 
-    results.sort_unstable_by(|a, b| {
+    results.sort_by(|a, b| {
         b.movement_pct
             .partial_cmp(&a.movement_pct)
             .unwrap_or(std::cmp::Ordering::Equal)
     });
 
-    if results.is_empty() {
-        return Ok(());
-    }
-
-    let output_data = OutputData {
-        last_updated_timestamp: max_close_time,
+    Some(OutputData {
+        last_updated_timestamp: last_updated,
         results,
-    };
+    })
+}
+
+// --- Main Execution (Refactored) ---
 
-    storage.save("results", &output_data).await?;
+/// Ranks the stored klines into the stored results, keeping a snapshot of the
+/// ranking under the configured retention policy.
+pub async fn run(
+    storage: &dyn Storage,
+    registry: &IndicatorRegistry,
+    snapshot_config: &SnapshotConfig,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    // Series are handed to the worker pool as they are read, so only the
+    // queued ones and those being analyzed are held in memory.
+    let (tx, rx) = mpsc::sync_channel::<KlineResult>(ANALYSIS_QUEUE);
+    let registry = registry.clone();
+    let workers = tokio::task::spawn_blocking(move || analyze_parallel(rx.into_iter(), &registry));
+    let read = storage
+        .for_each_series(&mut |series| {
+            if cancel.is_cancelled() {
+                return Err(Cancelled.into());
+            }
+            tx.send(series)
+                .map_err(|_| anyhow::anyhow!("Analysis workers stopped"))
+        })
+        .await;
+    drop(tx);
+    let analyzed = workers.await?;
+    read?;
+
+    let max_close_time = analyzed.iter().map(|(_, t)| *t).max().unwrap_or(0);
+    let results = analyzed.into_iter().map(|(item, _)| item).collect();
+    if let Some(output_data) = build_output(results, max_close_time) {
+        storage.save_results(&output_data).await?;
+        let taken_at = chrono::Utc::now().timestamp_millis();
+        snapshots::save(storage, &output_data, taken_at, snapshot_config).await?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,506 @@
+//! Exports the results, and optionally the raw klines, as CSV, NDJSON or
+//! Parquet for spreadsheets and notebooks.
+//!
+//! Both datasets are first laid out as a [`Frame`] of typed columns, which
+//! every format is then encoded from.
+
+use crate::indicators::IndicatorColumn;
+use crate::klines::{KLINE_KEYS, Kline, KlineResult};
+use crate::storage;
+use crate::storage_utils::{self, AsyncStorageManager};
+use crate::tui::OutputData;
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use parquet::basic::Compression;
+use parquet::column::writer::ColumnWriter;
+use parquet::data_type::ByteArray;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+// FRAME
+
+enum Values {
+    Int(Vec<Option<i64>>),
+    Float(Vec<Option<f64>>),
+    Text(Vec<Option<String>>),
+}
+
+impl Values {
+    fn json(&self, row: usize) -> Value {
+        match self {
+            Values::Int(v) => v[row].map_or(Value::Null, Value::from),
+            Values::Float(v) => v[row].map_or(Value::Null, Value::from),
+            Values::Text(v) => v[row].clone().map_or(Value::Null, Value::from),
+        }
+    }
+
+    fn text(&self, row: usize) -> String {
+        match self {
+            Values::Int(v) => v[row].map(|x| x.to_string()),
+            Values::Float(v) => v[row].map(|x| x.to_string()),
+            Values::Text(v) => v[row].clone(),
+        }
+        .unwrap_or_default()
+    }
+
+    fn parquet_type(&self) -> &'static str {
+        match self {
+            Values::Int(_) => "INT64",
+            Values::Float(_) => "DOUBLE",
+            Values::Text(_) => "BYTE_ARRAY",
+        }
+    }
+}
+
+/// A table of named, typed, nullable columns of equal length.
+pub struct Frame {
+    columns: Vec<(String, Values)>,
+    rows: usize,
+}
+
+/// One row per result, in rank order, with a column per indicator column.
+pub fn results_frame(data: &OutputData, indicator_columns: &[IndicatorColumn]) -> Frame {
+    let results = &data.results;
+    let text = |f: &dyn Fn(usize) -> String| -> Values {
+        Values::Text((0..results.len()).map(|i| Some(f(i))).collect())
+    };
+    let mut columns = vec![
+        (
+            "rank".to_string(),
+            Values::Int((1..=results.len() as i64).map(Some).collect()),
+        ),
+        ("symbol".to_string(), text(&|i| results[i].symbol.clone())),
+        (
+            "exchange".to_string(),
+            text(&|i| results[i].exchange.id().to_string()),
+        ),
+        (
+            "market".to_string(),
+            text(&|i| results[i].market.id().to_string()),
+        ),
+        (
+            "subType".to_string(),
+            text(&|i| results[i].sub_type.join(", ")),
+        ),
+        (
+            "movement_pct".to_string(),
+            Values::Float(results.iter().map(|r| Some(r.movement_pct)).collect()),
+        ),
+    ];
+    columns.extend(indicator_columns.iter().map(|column| {
+        (
+            column.key.to_string(),
+            Values::Float(
+                results
+                    .iter()
+                    .map(|r| r.indicators.get(column.key).copied())
+                    .collect(),
+            ),
+        )
+    }));
+    columns.push((
+        "last_updated_timestamp".to_string(),
+        Values::Int(vec![Some(data.last_updated_timestamp); results.len()]),
+    ));
+
+    Frame {
+        rows: results.len(),
+        columns,
+    }
+}
+
+/// One row per candle, keyed by exchange, market, symbol and interval.
+pub fn klines_frame(klines: &[KlineResult]) -> Frame {
+    let candles: Vec<(&KlineResult, &Kline)> = klines
+        .iter()
+        .flat_map(|series| series.klines.iter().map(move |k| (series, k)))
+        .collect();
+    let text = |f: fn(&KlineResult) -> String| -> Values {
+        Values::Text(candles.iter().map(|(series, _)| Some(f(series))).collect())
+    };
+
+    let mut columns = vec![
+        (
+            "exchange".to_string(),
+            text(|s| s.exchange.id().to_string()),
+        ),
+        ("market".to_string(), text(|s| s.market.id().to_string())),
+        ("symbol".to_string(), text(|s| s.symbol.clone())),
+        ("interval".to_string(), text(|s| s.interval.clone())),
+    ];
+    let int = |f: fn(&Kline) -> Option<i64>| -> Values {
+        Values::Int(candles.iter().map(|(_, k)| f(k)).collect())
+    };
+    let float = |f: fn(&Kline) -> Option<f64>| -> Values {
+        Values::Float(candles.iter().map(|(_, k)| f(k)).collect())
+    };
+    let fields = [
+        int(|k| Some(k.open_time)),
+        float(|k| Some(k.open)),
+        float(|k| Some(k.high)),
+        float(|k| Some(k.low)),
+        float(|k| Some(k.close)),
+        float(|k| Some(k.volume)),
+        int(|k| Some(k.close_time)),
+        float(|k| Some(k.quote_volume)),
+        int(|k| k.trades.map(|n| n as i64)),
+        float(|k| k.taker_buy_base_volume),
+        float(|k| k.taker_buy_quote_volume),
+    ];
+    columns.extend(KLINE_KEYS.iter().map(|k| k.to_string()).zip(fields));
+
+    Frame {
+        rows: candles.len(),
+        columns,
+    }
+}
+
+// ENCODING
+
+fn encode_csv(frame: &Frame) -> Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(frame.columns.iter().map(|(name, _)| name))?;
+    for row in 0..frame.rows {
+        writer.write_record(frame.columns.iter().map(|(_, values)| values.text(row)))?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+fn encode_ndjson(frame: &Frame) -> Result<Vec<u8>> {
+    // Fields are written by hand to keep them in column order.
+    let mut out = String::new();
+    for row in 0..frame.rows {
+        let fields: Vec<String> = frame
+            .columns
+            .iter()
+            .map(|(name, values)| {
+                Ok(format!(
+                    "{}:{}",
+                    serde_json::to_string(name)?,
+                    values.json(row)
+                ))
+            })
+            .collect::<Result<_>>()?;
+        out.push_str(&format!("{{{}}}\n", fields.join(",")));
+    }
+    Ok(out.into_bytes())
+}
+
+/// Splits nullable values into the present ones and their definition levels.
+fn split_nulls<T: Clone>(values: &[Option<T>]) -> (Vec<T>, Vec<i16>) {
+    let present = values.iter().flatten().cloned().collect();
+    let levels = values.iter().map(|v| v.is_some() as i16).collect();
+    (present, levels)
+}
+
+fn encode_parquet(frame: &Frame, name: &str) -> Result<Vec<u8>> {
+    let fields: Vec<String> = frame
+        .columns
+        .iter()
+        .map(|(column, values)| match values {
+            Values::Text(_) => format!("OPTIONAL BYTE_ARRAY {} (UTF8);", column),
+            _ => format!("OPTIONAL {} {};", values.parquet_type(), column),
+        })
+        .collect();
+    let schema = parse_message_type(&format!("message {} {{ {} }}", name, fields.join(" ")))?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+
+    let mut out = Vec::new();
+    let mut writer = SerializedFileWriter::new(&mut out, Arc::new(schema), Arc::new(properties))?;
+    let mut row_group = writer.next_row_group()?;
+    let mut columns = frame.columns.iter();
+    while let Some(mut column_writer) = row_group.next_column()? {
+        let Some((column, values)) = columns.next() else {
+            break;
+        };
+        match (column_writer.untyped(), values) {
+            (ColumnWriter::Int64ColumnWriter(w), Values::Int(v)) => {
+                let (present, levels) = split_nulls(v);
+                w.write_batch(&present, Some(&levels), None)?;
+            }
+            (ColumnWriter::DoubleColumnWriter(w), Values::Float(v)) => {
+                let (present, levels) = split_nulls(v);
+                w.write_batch(&present, Some(&levels), None)?;
+            }
+            (ColumnWriter::ByteArrayColumnWriter(w), Values::Text(v)) => {
+                let (present, levels) = split_nulls(v);
+                let present: Vec<ByteArray> = present
+                    .iter()
+                    .map(|s| ByteArray::from(s.as_str()))
+                    .collect();
+                w.write_batch(&present, Some(&levels), None)?;
+            }
+            _ => bail!("column {} does not match its Parquet type", column),
+        }
+        column_writer.close()?;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(out)
+}
+
+pub fn encode(frame: &Frame, format: ExportFormat, name: &str) -> Result<Vec<u8>> {
+    match format {
+        ExportFormat::Csv => encode_csv(frame),
+        ExportFormat::Ndjson => encode_ndjson(frame),
+        ExportFormat::Parquet => encode_parquet(frame, name),
+    }
+}
+
+// MAIN
+
+/// Writes the stored results, and the stored klines when `include_klines` is
+/// set, into `dir` (`exports/` in storage by default) as files named after
+/// the current time. Returns the paths written.
+pub async fn export(
+    format: ExportFormat,
+    include_klines: bool,
+    dir: Option<&Path>,
+) -> Result<Vec<PathBuf>> {
+    let dir = match dir {
+        Some(dir) => dir.to_path_buf(),
+        None => AsyncStorageManager::open().await?.base_dir.join("exports"),
+    };
+    let app_config = storage_utils::load_config().await?;
+    let storage = storage::open(&app_config).await?;
+    let indicator_columns = app_config.indicator_registry()?.columns();
+    tokio::fs::create_dir_all(&dir).await?;
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+
+    let mut frames = Vec::new();
+    let data: OutputData = storage.load_results().await?;
+    frames.push(("results", results_frame(&data, &indicator_columns)));
+    if include_klines {
+        let klines = storage.load_klines().await?;
+        frames.push(("klines", klines_frame(&klines)));
+    }
+
+    let mut written = Vec::new();
+    for (name, frame) in frames {
+        let path = dir.join(format!("{}_{}.{}", name, stamp, format.extension()));
+        tokio::fs::write(&path, encode(&frame, format, name)?).await?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicators::Coloring;
+    use crate::storage_utils::{Exchange, Market};
+    use crate::tui::AssetResult;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+    use std::collections::BTreeMap;
+
+    fn results() -> (OutputData, Vec<IndicatorColumn>) {
+        let result = |symbol: &str, movement_pct: f64, rsi: Option<f64>| AssetResult {
+            symbol: symbol.to_string(),
+            exchange: Exchange::Bybit,
+            market: Market::UsdM,
+            sub_type: vec!["Layer-1".to_string(), "PoW".to_string()],
+            movement_pct,
+            indicators: rsi.map(|v| ("rsi".to_string(), v)).into_iter().collect(),
+        };
+        let data = OutputData {
+            last_updated_timestamp: 1_700_000_000_000,
+            results: vec![
+                result("BTCUSDT", 4.25, Some(71.5)),
+                result("ETHUSDT", -1.5, None),
+            ],
+            fetch_errors: Vec::new(),
+        };
+        let columns = vec![IndicatorColumn {
+            key: "rsi",
+            header: "RSI",
+            decimals: 2,
+            coloring: Coloring::Plain,
+        }];
+        (data, columns)
+    }
+
+    fn klines() -> Vec<KlineResult> {
+        let mut candle = Kline::flat(60_000, 10.5, 60_000);
+        candle.trades = Some(42);
+        candle.taker_buy_quote_volume = Some(6.25);
+        vec![KlineResult {
+            symbol: "BTCUSDT".to_string(),
+            exchange: Exchange::Okx,
+            market: Market::Spot,
+            interval: "1m".to_string(),
+            underlying_sub_type: Vec::new(),
+            klines: vec![Kline::flat(0, 10.0, 60_000), candle],
+        }]
+    }
+
+    /// The frame's rows as the JSON values each format should decode back to.
+    fn rows(frame: &Frame) -> Vec<Vec<(String, Value)>> {
+        (0..frame.rows)
+            .map(|row| {
+                frame
+                    .columns
+                    .iter()
+                    .map(|(name, values)| (name.clone(), values.json(row)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn frames() -> Vec<Frame> {
+        let (data, columns) = results();
+        vec![results_frame(&data, &columns), klines_frame(&klines())]
+    }
+
+    #[test]
+    fn results_frame_ranks_results_and_leaves_missing_indicators_empty() {
+        let (data, columns) = results();
+        let rows = rows(&results_frame(&data, &columns));
+        let names: Vec<&str> = rows[0].iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "rank",
+                "symbol",
+                "exchange",
+                "market",
+                "subType",
+                "movement_pct",
+                "rsi",
+                "last_updated_timestamp"
+            ]
+        );
+        let values: Vec<&Value> = rows[1].iter().map(|(_, value)| value).collect();
+        assert_eq!(
+            values,
+            [
+                &Value::from(2),
+                &Value::from("ETHUSDT"),
+                &Value::from("bybit"),
+                &Value::from("usdt_m"),
+                &Value::from("Layer-1, PoW"),
+                &Value::from(-1.5),
+                &Value::Null,
+                &Value::from(1_700_000_000_000i64),
+            ]
+        );
+    }
+
+    #[test]
+    fn klines_frame_has_a_row_per_candle() {
+        let rows = rows(&klines_frame(&klines()));
+        assert_eq!(rows.len(), 2);
+        let row: BTreeMap<&str, &Value> = rows[1].iter().map(|(k, v)| (k.as_str(), v)).collect();
+        assert_eq!(row["exchange"], &Value::from("okx"));
+        assert_eq!(row["market"], &Value::from("spot"));
+        assert_eq!(row["interval"], &Value::from("1m"));
+        assert_eq!(row["openTime"], &Value::from(60_000));
+        assert_eq!(row["closeTime"], &Value::from(119_999));
+        assert_eq!(row["close"], &Value::from(10.5));
+        assert_eq!(row["numberOfTrades"], &Value::from(42));
+        assert_eq!(row["takerBuyBaseAssetVolume"], &Value::Null);
+        assert_eq!(row["takerBuyQuoteAssetVolume"], &Value::from(6.25));
+    }
+
+    #[test]
+    fn csv_round_trips() {
+        for frame in frames() {
+            let bytes = encode(&frame, ExportFormat::Csv, "test").unwrap();
+            let mut reader = csv::Reader::from_reader(bytes.as_slice());
+            let headers: Vec<String> = reader.headers().unwrap().iter().map(String::from).collect();
+            let records: Vec<Vec<String>> = reader
+                .records()
+                .map(|r| r.unwrap().iter().map(String::from).collect())
+                .collect();
+
+            let expected: Vec<Vec<String>> = (0..frame.rows)
+                .map(|row| frame.columns.iter().map(|(_, v)| v.text(row)).collect())
+                .collect();
+            let names: Vec<String> = frame.columns.iter().map(|(n, _)| n.clone()).collect();
+            assert_eq!(headers, names);
+            assert_eq!(records, expected);
+        }
+    }
+
+    #[test]
+    fn ndjson_round_trips_in_column_order() {
+        for frame in frames() {
+            let bytes = encode(&frame, ExportFormat::Ndjson, "test").unwrap();
+            let text = String::from_utf8(bytes).unwrap();
+            let lines: Vec<&str> = text.lines().collect();
+            assert_eq!(lines.len(), frame.rows);
+
+            for (line, row) in lines.iter().zip(rows(&frame)) {
+                let decoded: Value = serde_json::from_str(line).unwrap();
+                let expected: serde_json::Map<String, Value> = row.iter().cloned().collect();
+                assert_eq!(decoded, Value::Object(expected));
+
+                let positions: Vec<usize> = row
+                    .iter()
+                    .map(|(name, _)| line.find(&format!("\"{}\":", name)).unwrap())
+                    .collect();
+                assert!(positions.is_sorted(), "{}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn parquet_round_trips() {
+        let path = std::env::temp_dir().join(format!("screener-export-{}", std::process::id()));
+        for frame in frames() {
+            std::fs::write(
+                &path,
+                encode(&frame, ExportFormat::Parquet, "test").unwrap(),
+            )
+            .unwrap();
+            let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+            let decoded: Vec<Vec<(String, Value)>> = reader
+                .get_row_iter(None)
+                .unwrap()
+                .map(|row| {
+                    row.unwrap()
+                        .get_column_iter()
+                        .map(|(name, field)| {
+                            let value = match field {
+                                Field::Null => Value::Null,
+                                Field::Long(v) => Value::from(*v),
+                                Field::Double(v) => Value::from(*v),
+                                Field::Str(v) => Value::from(v.as_str()),
+                                other => panic!("unexpected field {:?}", other),
+                            };
+                            (name.clone(), value)
+                        })
+                        .collect()
+                })
+                .collect();
+            assert_eq!(decoded, rows(&frame));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,9 +1,13 @@
-use crate::storage_utils::AsyncStorageManager;
+use crate::analysis::Cancelled;
+use crate::market_data::{self, MarketDataSource};
+use crate::retry;
+use crate::storage::Storage;
+use crate::storage_utils::RetryConfig;
 use anyhow::Result;
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use std::collections::HashMap;
+use tokio_util::sync::CancellationToken;
 
 // DATA
 
@@ -24,7 +28,10 @@ pub struct RateLimit {
 
 // FILTER
 
-fn matches_filters(symbol: &Map<String, Value>, filters: &HashMap<String, String>) -> bool {
+pub(crate) fn matches_filters(
+    symbol: &Map<String, Value>,
+    filters: &HashMap<String, String>,
+) -> bool {
     for (key, required_value) in filters {
         match symbol.get(key) {
             Some(Value::String(s)) => {
@@ -66,17 +73,22 @@ fn matches_filters(symbol: &Map<String, Value>, filters: &HashMap<String, String
 
 // MAIN
 
-pub async fn fetch_exchange_info(filters: &HashMap<String, String>) -> Result<()> {
-    let storage = AsyncStorageManager::new_relative("storage").await?;
-
-    let client = Client::new();
-    let response = client
-        .get("https://fapi.binance.com/fapi/v1/exchangeInfo")
-        .send()
-        .await?
-        .error_for_status()?;
-
-    let exchange_info: ExchangeInfo = response.json().await?;
+pub async fn fetch_exchange_info(
+    storage: &dyn Storage,
+    source: &dyn MarketDataSource,
+    filters: &HashMap<String, String>,
+    retry_config: &RetryConfig,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let request = retry::with_retry(
+        retry_config,
+        |e| retry::is_retryable_anyhow(e, retry_config),
+        || source.list_symbols(),
+    );
+    let exchange_info = tokio::select! {
+        result = request => result?,
+        _ = cancel.cancelled() => return Err(Cancelled.into()),
+    };
 
     // We keep the logic, just remove the print statements.
     let _matching_count = exchange_info
@@ -85,7 +97,9 @@ pub async fn fetch_exchange_info(filters: &HashMap<String, String>) -> Result<()
         .filter(|s| matches_filters(s, filters))
         .count();
 
-    storage.save("exchange_info", &exchange_info).await?;
+    storage
+        .save_exchange_info(&market_data::exchange_info_key(source), &exchange_info)
+        .await?;
 
     Ok(())
 }
@@ -1,19 +1,1286 @@
-use crate::cumulative_price_change::InputKline;
-use ta::Next;
-use ta::indicators::RelativeStrengthIndex;
+//! Per-symbol indicators computed during analysis.
+//!
+//! Every [`Indicator`] reads a symbol's candles and fills the columns it
+//! declares. The [`IndicatorRegistry`] holds the indicators listed under
+//! `indicators` in the configuration, and the results, the TUI, the `show`
+//! table and the exports are all laid out from it.
 
-pub fn calculate_rsi(klines: &[InputKline], period: u32) -> Option<f64> {
-    let mut rsi_indicator = RelativeStrengthIndex::new(period as usize).ok()?;
+use crate::klines::Kline;
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use ta::indicators::{
+    AverageTrueRange, BollingerBands, RelativeStrengthIndex, SimpleMovingAverage,
+};
+use ta::{Close, High, Low, Next};
 
-    let close_prices: Vec<f64> = klines.iter().filter_map(|kline| kline.close).collect();
+// CONFIG
 
-    if close_prices.len() < period as usize {
-        return None;
+/// One entry of `indicators` in the configuration, tagged by `name`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "name", rename_all = "snake_case")]
+pub enum IndicatorConfig {
+    Rsi {
+        #[serde(default = "default_rsi_period")]
+        period: u32,
+    },
+    Macd {
+        #[serde(default = "default_macd_fast")]
+        fast: u32,
+        #[serde(default = "default_macd_slow")]
+        slow: u32,
+        #[serde(default = "default_macd_signal")]
+        signal: u32,
+    },
+    /// Crossings of a fast and a slow moving average.
+    MaCross {
+        #[serde(default)]
+        average: MovingAverage,
+        #[serde(default = "default_cross_fast")]
+        fast: u32,
+        #[serde(default = "default_cross_slow")]
+        slow: u32,
+    },
+    Bollinger {
+        #[serde(default = "default_bollinger_period")]
+        period: u32,
+        /// Width of the bands in standard deviations.
+        #[serde(default = "default_bollinger_multiplier")]
+        multiplier: f64,
+    },
+    Atr {
+        #[serde(default = "default_atr_period")]
+        period: u32,
+    },
+    /// Annualized standard deviation of log returns.
+    RealizedVol {
+        #[serde(default = "default_realized_vol_period")]
+        period: u32,
+    },
+    /// ATR over the recent candles against ATR over the long run.
+    RangeExpansion {
+        #[serde(default = "default_atr_period")]
+        recent: u32,
+        #[serde(default = "default_range_long")]
+        long: u32,
+    },
+    /// Quote volume and trade counts of the last `recent` candles against
+    /// the `lookback` candles before them.
+    Volume {
+        #[serde(default = "default_volume_recent")]
+        recent: u32,
+        #[serde(default = "default_volume_lookback")]
+        lookback: u32,
+    },
+    /// Taker buy ratio and cumulative volume delta over the whole series and
+    /// over the last `recent` candles.
+    TakerFlow {
+        #[serde(default = "default_taker_recent")]
+        recent: u32,
+    },
+}
+
+impl IndicatorConfig {
+    /// Every indicator with its default parameters, RSI over `rsi_period`.
+    pub fn defaults(rsi_period: u32) -> Vec<IndicatorConfig> {
+        vec![
+            IndicatorConfig::Rsi { period: rsi_period },
+            IndicatorConfig::Macd {
+                fast: default_macd_fast(),
+                slow: default_macd_slow(),
+                signal: default_macd_signal(),
+            },
+            IndicatorConfig::MaCross {
+                average: MovingAverage::default(),
+                fast: default_cross_fast(),
+                slow: default_cross_slow(),
+            },
+            IndicatorConfig::Bollinger {
+                period: default_bollinger_period(),
+                multiplier: default_bollinger_multiplier(),
+            },
+            IndicatorConfig::Atr {
+                period: default_atr_period(),
+            },
+            IndicatorConfig::RealizedVol {
+                period: default_realized_vol_period(),
+            },
+            IndicatorConfig::RangeExpansion {
+                recent: default_atr_period(),
+                long: default_range_long(),
+            },
+            IndicatorConfig::Volume {
+                recent: default_volume_recent(),
+                lookback: default_volume_lookback(),
+            },
+            IndicatorConfig::TakerFlow {
+                recent: default_taker_recent(),
+            },
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MovingAverage {
+    #[default]
+    Ema,
+    Sma,
+}
+
+pub fn default_rsi_period() -> u32 {
+    14
+}
+
+fn default_macd_fast() -> u32 {
+    12
+}
+
+fn default_macd_slow() -> u32 {
+    26
+}
+
+fn default_macd_signal() -> u32 {
+    9
+}
+
+fn default_cross_fast() -> u32 {
+    9
+}
+
+fn default_cross_slow() -> u32 {
+    21
+}
+
+fn default_bollinger_period() -> u32 {
+    20
+}
+
+fn default_bollinger_multiplier() -> f64 {
+    2.0
+}
+
+fn default_atr_period() -> u32 {
+    14
+}
+
+fn default_realized_vol_period() -> u32 {
+    30
+}
+
+fn default_range_long() -> u32 {
+    100
+}
+
+fn default_volume_recent() -> u32 {
+    5
+}
+
+fn default_volume_lookback() -> u32 {
+    100
+}
+
+fn default_taker_recent() -> u32 {
+    20
+}
+
+// INDICATOR TRAIT
+
+/// How the values of a column are colored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coloring {
+    Plain,
+    /// Values at or above `high` are red, at or below `low` green.
+    Bands {
+        low: f64,
+        high: f64,
+    },
+    /// Positive values are green, negative ones red.
+    Sign,
+    /// Values at or above `above` stand out in yellow.
+    Highlight {
+        above: f64,
+    },
+}
+
+/// One value an indicator adds to each result.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndicatorColumn {
+    /// Key of the value in the results' `indicators` map.
+    pub key: &'static str,
+    pub header: &'static str,
+    /// Decimal places the value is shown with.
+    pub decimals: usize,
+    pub coloring: Coloring,
+}
+
+pub trait Indicator: Send + Sync {
+    /// Identifier used as `name` in the configuration.
+    fn name(&self) -> &'static str;
+
+    /// Human readable name, shown in the TUI sidebar.
+    fn label(&self) -> &'static str;
+
+    /// The configured parameters, by name.
+    fn params(&self) -> Vec<(&'static str, f64)>;
+
+    /// Candles needed before the indicator yields a value.
+    fn warm_up(&self) -> usize;
+
+    fn columns(&self) -> Vec<IndicatorColumn>;
+
+    /// One value per column, in [`columns`](Indicator::columns) order. Only
+    /// called with at least [`warm_up`](Indicator::warm_up) candles.
+    fn compute(&self, klines: &[Kline]) -> Vec<Option<f64>>;
+}
+
+/// The label followed by the parameter values, e.g. `Relative Strength Index (14)`.
+pub fn title(indicator: &dyn Indicator) -> String {
+    let params: Vec<String> = indicator
+        .params()
+        .iter()
+        .map(|(_, value)| value.to_string())
+        .collect();
+    if params.is_empty() {
+        indicator.label().to_string()
+    } else {
+        format!("{} ({})", indicator.label(), params.join(", "))
+    }
+}
+
+fn closes(klines: &[Kline]) -> impl Iterator<Item = f64> + '_ {
+    klines.iter().map(|k| k.close)
+}
+
+/// The last `count` candles, unset when the series is shorter.
+fn last_candles(klines: &[Kline], count: usize) -> Option<&[Kline]> {
+    klines
+        .len()
+        .checked_sub(count)
+        .map(|start| &klines[start..])
+}
+
+/// Feeds every close to a `ta` indicator, returning its last output.
+fn last_output<I: Next<f64>>(mut indicator: I, klines: &[Kline]) -> Option<I::Output> {
+    closes(klines).map(|close| indicator.next(close)).last()
+}
+
+/// The exponential average after each value, seeded with the SMA of the first
+/// `period` values rather than the first value like `ta`'s, so it is settled
+/// from the `period`th value on; before that it is a partial SMA.
+fn ema(period: usize, values: impl Iterator<Item = f64>) -> Vec<f64> {
+    let Ok(mut sma) = SimpleMovingAverage::new(period) else {
+        return Vec::new();
+    };
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut ema = None;
+    values
+        .enumerate()
+        .map(|(i, v)| {
+            let value = match ema {
+                Some(prev) => alpha * v + (1.0 - alpha) * prev,
+                None => sma.next(v),
+            };
+            if i + 1 >= period {
+                ema = Some(value);
+            }
+            value
+        })
+        .collect()
+}
+
+impl MovingAverage {
+    /// The average after each close, see [`ema`] for how the EMA is seeded.
+    fn series(self, period: u32, klines: &[Kline]) -> Vec<f64> {
+        let period = period as usize;
+        match self {
+            MovingAverage::Ema => ema(period, closes(klines)),
+            MovingAverage::Sma => match SimpleMovingAverage::new(period) {
+                Ok(mut sma) => closes(klines).map(|c| sma.next(c)).collect(),
+                Err(_) => Vec::new(),
+            },
+        }
+    }
+}
+
+impl High for Kline {
+    fn high(&self) -> f64 {
+        self.high
+    }
+}
+
+impl Low for Kline {
+    fn low(&self) -> f64 {
+        self.low
+    }
+}
+
+impl Close for Kline {
+    fn close(&self) -> f64 {
+        self.close
+    }
+}
+
+/// Average true range over `period` candles, after the last one.
+fn atr(period: u32, klines: &[Kline]) -> Option<f64> {
+    let mut atr = AverageTrueRange::new(period as usize).ok()?;
+    klines.iter().map(|k| atr.next(k)).last()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Sample standard deviation, needs at least two values.
+fn std_dev(values: &[f64]) -> f64 {
+    let mean = mean(values);
+    let squares: f64 = values.iter().map(|v| (v - mean).powi(2)).sum();
+    (squares / (values.len() - 1) as f64).sqrt()
+}
+
+const MS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0 * 1000.0;
+
+// INDICATORS
+
+pub struct Rsi {
+    period: u32,
+}
+
+impl Indicator for Rsi {
+    fn name(&self) -> &'static str {
+        "rsi"
+    }
+
+    fn label(&self) -> &'static str {
+        "Relative Strength Index"
+    }
+
+    fn params(&self) -> Vec<(&'static str, f64)> {
+        vec![("period", self.period as f64)]
+    }
+
+    fn warm_up(&self) -> usize {
+        self.period as usize
+    }
+
+    fn columns(&self) -> Vec<IndicatorColumn> {
+        vec![IndicatorColumn {
+            key: "rsi",
+            header: "RSI",
+            decimals: 2,
+            coloring: Coloring::Bands {
+                low: 30.0,
+                high: 70.0,
+            },
+        }]
+    }
+
+    fn compute(&self, klines: &[Kline]) -> Vec<Option<f64>> {
+        let Ok(rsi) = RelativeStrengthIndex::new(self.period as usize) else {
+            return vec![None];
+        };
+        vec![last_output(rsi, klines)]
+    }
+}
+
+pub struct Macd {
+    fast: u32,
+    slow: u32,
+    signal: u32,
+}
+
+impl Indicator for Macd {
+    fn name(&self) -> &'static str {
+        "macd"
+    }
+
+    fn label(&self) -> &'static str {
+        "MACD"
+    }
+
+    fn params(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("fast", self.fast as f64),
+            ("slow", self.slow as f64),
+            ("signal", self.signal as f64),
+        ]
+    }
+
+    fn warm_up(&self) -> usize {
+        (self.slow + self.signal) as usize
+    }
+
+    fn columns(&self) -> Vec<IndicatorColumn> {
+        vec![
+            IndicatorColumn {
+                key: "macd_hist",
+                header: "MACD Hist",
+                decimals: 4,
+                coloring: Coloring::Sign,
+            },
+            IndicatorColumn {
+                key: "macd_hist_pct",
+                header: "Hist (% of price)",
+                decimals: 3,
+                coloring: Coloring::Sign,
+            },
+        ]
+    }
+
+    /// The histogram, and the same as a percentage of the last close so it
+    /// compares across symbols. Built on the same EMA as `ma_cross`, with the
+    /// signal line starting once the slow EMA has settled.
+    fn compute(&self, klines: &[Kline]) -> Vec<Option<f64>> {
+        let fast = MovingAverage::Ema.series(self.fast, klines);
+        let slow = MovingAverage::Ema.series(self.slow, klines);
+        let macd: Vec<f64> = fast
+            .iter()
+            .zip(&slow)
+            .skip(self.slow as usize - 1)
+            .map(|(f, s)| f - s)
+            .collect();
+        let signal = ema(self.signal as usize, macd.iter().copied());
+        let histogram = macd.last().zip(signal.last()).map(|(m, s)| m - s);
+        let close = klines.last().map(|k| k.close);
+        vec![histogram, histogram.zip(close).map(|(h, c)| h / c * 100.0)]
+    }
+}
+
+pub struct MaCross {
+    average: MovingAverage,
+    fast: u32,
+    slow: u32,
+}
+
+impl Indicator for MaCross {
+    fn name(&self) -> &'static str {
+        "ma_cross"
+    }
+
+    fn label(&self) -> &'static str {
+        match self.average {
+            MovingAverage::Ema => "EMA Crossover",
+            MovingAverage::Sma => "SMA Crossover",
+        }
+    }
+
+    fn params(&self) -> Vec<(&'static str, f64)> {
+        vec![("fast", self.fast as f64), ("slow", self.slow as f64)]
+    }
+
+    fn warm_up(&self) -> usize {
+        self.slow as usize
+    }
+
+    fn columns(&self) -> Vec<IndicatorColumn> {
+        vec![
+            IndicatorColumn {
+                key: "cross_bars",
+                header: "Bars Since Cross",
+                decimals: 0,
+                coloring: Coloring::Plain,
+            },
+            IndicatorColumn {
+                key: "cross_spread_pct",
+                header: "Fast vs Slow (%)",
+                decimals: 2,
+                coloring: Coloring::Sign,
+            },
+        ]
+    }
+
+    /// Candles since the fast average last crossed the slow one, unset when
+    /// it did not within the series, and how far the fast average is above
+    /// the slow one, which tells the direction of that cross.
+    fn compute(&self, klines: &[Kline]) -> Vec<Option<f64>> {
+        let fast = self.average.series(self.fast, klines);
+        let slow = self.average.series(self.slow, klines);
+        // Before `slow` candles the slow average is still warming up.
+        let spreads: Vec<f64> = fast
+            .iter()
+            .zip(&slow)
+            .skip(self.slow as usize - 1)
+            .map(|(f, s)| f - s)
+            .collect();
+
+        let last_cross = spreads
+            .windows(2)
+            .rposition(|pair| (pair[0] > 0.0) != (pair[1] > 0.0));
+        let bars = last_cross.map(|i| (spreads.len() - 2 - i) as f64);
+        let spread_pct = fast
+            .last()
+            .zip(slow.last())
+            .map(|(f, s)| (f / s - 1.0) * 100.0);
+        vec![bars, spread_pct]
+    }
+}
+
+pub struct Bollinger {
+    period: u32,
+    multiplier: f64,
+}
+
+impl Indicator for Bollinger {
+    fn name(&self) -> &'static str {
+        "bollinger"
+    }
+
+    fn label(&self) -> &'static str {
+        "Bollinger Bands"
+    }
+
+    fn params(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("period", self.period as f64),
+            ("multiplier", self.multiplier),
+        ]
+    }
+
+    fn warm_up(&self) -> usize {
+        self.period as usize
+    }
+
+    fn columns(&self) -> Vec<IndicatorColumn> {
+        vec![
+            IndicatorColumn {
+                key: "bb_percent_b",
+                header: "%B",
+                decimals: 2,
+                coloring: Coloring::Bands {
+                    low: 0.0,
+                    high: 1.0,
+                },
+            },
+            IndicatorColumn {
+                key: "bb_bandwidth",
+                header: "Bandwidth (%)",
+                decimals: 2,
+                coloring: Coloring::Plain,
+            },
+        ]
+    }
+
+    /// Where the last close sits between the bands, 0 at the lower and 1 at
+    /// the upper one, and the width of the bands as a percentage of their
+    /// middle.
+    fn compute(&self, klines: &[Kline]) -> Vec<Option<f64>> {
+        let Ok(bands) = BollingerBands::new(self.period as usize, self.multiplier) else {
+            return vec![None, None];
+        };
+        let (Some(output), Some(last)) = (last_output(bands, klines), klines.last()) else {
+            return vec![None, None];
+        };
+        let width = output.upper - output.lower;
+        vec![
+            Some((last.close - output.lower) / width),
+            Some(width / output.average * 100.0),
+        ]
+    }
+}
+
+pub struct Atr {
+    period: u32,
+}
+
+impl Indicator for Atr {
+    fn name(&self) -> &'static str {
+        "atr"
+    }
+
+    fn label(&self) -> &'static str {
+        "Average True Range"
+    }
+
+    fn params(&self) -> Vec<(&'static str, f64)> {
+        vec![("period", self.period as f64)]
+    }
+
+    fn warm_up(&self) -> usize {
+        self.period as usize
+    }
+
+    fn columns(&self) -> Vec<IndicatorColumn> {
+        vec![
+            IndicatorColumn {
+                key: "atr",
+                header: "ATR",
+                decimals: 4,
+                coloring: Coloring::Plain,
+            },
+            IndicatorColumn {
+                key: "atr_pct",
+                header: "ATR (% of price)",
+                decimals: 2,
+                coloring: Coloring::Plain,
+            },
+        ]
+    }
+
+    fn compute(&self, klines: &[Kline]) -> Vec<Option<f64>> {
+        let atr = atr(self.period, klines);
+        let close = klines.last().map(|k| k.close);
+        vec![atr, atr.zip(close).map(|(a, c)| a / c * 100.0)]
+    }
+}
+
+pub struct RealizedVol {
+    period: u32,
+}
+
+impl Indicator for RealizedVol {
+    fn name(&self) -> &'static str {
+        "realized_vol"
+    }
+
+    fn label(&self) -> &'static str {
+        "Realized Volatility"
+    }
+
+    fn params(&self) -> Vec<(&'static str, f64)> {
+        vec![("period", self.period as f64)]
+    }
+
+    /// One close more than returns, to have a return for each of them.
+    fn warm_up(&self) -> usize {
+        self.period as usize + 1
+    }
+
+    fn columns(&self) -> Vec<IndicatorColumn> {
+        vec![IndicatorColumn {
+            key: "realized_vol",
+            header: "Realized Vol (%)",
+            decimals: 1,
+            coloring: Coloring::Plain,
+        }]
+    }
+
+    /// Sample standard deviation of the last `period` log returns, scaled to
+    /// a year of candles of the series' interval, as a percentage.
+    fn compute(&self, klines: &[Kline]) -> Vec<Option<f64>> {
+        let Some(recent) = last_candles(klines, self.warm_up()) else {
+            return vec![None];
+        };
+        let returns: Vec<f64> = recent
+            .windows(2)
+            .map(|pair| (pair[1].close / pair[0].close).ln())
+            .collect();
+        if returns.len() < 2 {
+            return vec![None];
+        }
+
+        let last = &recent[recent.len() - 1];
+        let interval_ms = (last.close_time - last.open_time + 1) as f64;
+        if interval_ms <= 0.0 {
+            return vec![None];
+        }
+        vec![Some(
+            std_dev(&returns) * (MS_PER_YEAR / interval_ms).sqrt() * 100.0,
+        )]
+    }
+}
+
+pub struct RangeExpansion {
+    recent: u32,
+    long: u32,
+}
+
+impl Indicator for RangeExpansion {
+    fn name(&self) -> &'static str {
+        "range_expansion"
+    }
+
+    fn label(&self) -> &'static str {
+        "Range Expansion"
+    }
+
+    fn params(&self) -> Vec<(&'static str, f64)> {
+        vec![("recent", self.recent as f64), ("long", self.long as f64)]
+    }
+
+    fn warm_up(&self) -> usize {
+        self.long as usize
+    }
+
+    fn columns(&self) -> Vec<IndicatorColumn> {
+        vec![IndicatorColumn {
+            key: "range_expansion",
+            header: "Recent / Long ATR",
+            decimals: 2,
+            coloring: Coloring::Highlight { above: 1.5 },
+        }]
+    }
+
+    /// Above 1 when the candles have recently been ranging wider than usual.
+    fn compute(&self, klines: &[Kline]) -> Vec<Option<f64>> {
+        let recent = atr(self.recent, klines);
+        let long = atr(self.long, klines);
+        vec![recent.zip(long).map(|(r, l)| r / l)]
+    }
+}
+
+pub struct Volume {
+    recent: u32,
+    lookback: u32,
+}
+
+impl Indicator for Volume {
+    fn name(&self) -> &'static str {
+        "volume"
+    }
+
+    fn label(&self) -> &'static str {
+        "Volume Activity"
+    }
+
+    fn params(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("recent", self.recent as f64),
+            ("lookback", self.lookback as f64),
+        ]
+    }
+
+    fn warm_up(&self) -> usize {
+        (self.recent + self.lookback) as usize
+    }
+
+    fn columns(&self) -> Vec<IndicatorColumn> {
+        vec![
+            IndicatorColumn {
+                key: "rvol",
+                header: "Rel. Volume",
+                decimals: 2,
+                coloring: Coloring::Highlight { above: 2.0 },
+            },
+            IndicatorColumn {
+                key: "volume_z",
+                header: "Volume Z",
+                decimals: 2,
+                coloring: Coloring::Highlight { above: 2.0 },
+            },
+            IndicatorColumn {
+                key: "trades_ratio",
+                header: "Trades Ratio",
+                decimals: 2,
+                coloring: Coloring::Highlight { above: 2.0 },
+            },
+        ]
+    }
+
+    /// Average quote volume of the recent candles over that of the lookback
+    /// candles, the z-score of the last candle's quote volume against the
+    /// lookback candles, and the same ratio as the first for trade counts.
+    /// The trade ratio is unset when the exchange does not report trade counts.
+    fn compute(&self, klines: &[Kline]) -> Vec<Option<f64>> {
+        let Some(window) = last_candles(klines, self.warm_up()) else {
+            return vec![None; 3];
+        };
+        let (lookback, recent) = window.split_at(self.lookback as usize);
+
+        let volumes =
+            |candles: &[Kline]| -> Vec<f64> { candles.iter().map(|k| k.quote_volume).collect() };
+        let (recent_volume, lookback_volume) = (mean(&volumes(recent)), volumes(lookback));
+        let rvol = recent_volume / mean(&lookback_volume);
+        let last_volume = recent[recent.len() - 1].quote_volume;
+        let z = if lookback_volume.len() < 2 {
+            None
+        } else {
+            Some((last_volume - mean(&lookback_volume)) / std_dev(&lookback_volume))
+        };
+
+        let trades = |candles: &[Kline]| -> Option<Vec<f64>> {
+            candles.iter().map(|k| k.trades.map(|n| n as f64)).collect()
+        };
+        let trades_ratio = trades(recent)
+            .zip(trades(lookback))
+            .map(|(recent, lookback)| mean(&recent) / mean(&lookback));
+
+        vec![Some(rvol), z, trades_ratio]
+    }
+}
+
+pub struct TakerFlow {
+    recent: u32,
+}
+
+/// Taker buy quote volume and total quote volume of the candles, unset when
+/// the exchange does not report taker volume.
+fn taker_volumes(klines: &[Kline]) -> Option<(f64, f64)> {
+    klines.iter().try_fold((0.0, 0.0), |(buy, total), k| {
+        Some((buy + k.taker_buy_quote_volume?, total + k.quote_volume))
+    })
+}
+
+impl Indicator for TakerFlow {
+    fn name(&self) -> &'static str {
+        "taker_flow"
+    }
+
+    fn label(&self) -> &'static str {
+        "Taker Pressure"
+    }
+
+    fn params(&self) -> Vec<(&'static str, f64)> {
+        vec![("recent", self.recent as f64)]
+    }
+
+    fn warm_up(&self) -> usize {
+        self.recent as usize
     }
 
-    let mut last_rsi: Option<f64> = None;
-    for price in close_prices {
-        last_rsi = Some(rsi_indicator.next(price));
+    fn columns(&self) -> Vec<IndicatorColumn> {
+        vec![
+            IndicatorColumn {
+                key: "taker_buy_ratio",
+                header: "Buy Ratio",
+                decimals: 3,
+                coloring: Coloring::Plain,
+            },
+            IndicatorColumn {
+                key: "cvd",
+                header: "CVD",
+                decimals: 0,
+                coloring: Coloring::Sign,
+            },
+            IndicatorColumn {
+                key: "taker_buy_ratio_recent",
+                header: "Recent Buy Ratio",
+                decimals: 3,
+                coloring: Coloring::Plain,
+            },
+            IndicatorColumn {
+                key: "cvd_recent",
+                header: "Recent CVD",
+                decimals: 0,
+                coloring: Coloring::Sign,
+            },
+            IndicatorColumn {
+                key: "delta_divergence",
+                header: "Divergence",
+                decimals: 0,
+                coloring: Coloring::Sign,
+            },
+        ]
+    }
+
+    /// The share of quote volume bought by takers and the cumulative volume
+    /// delta, taker buys minus taker sells in quote volume, for the series
+    /// and for the recent candles. The divergence is 1 when price fell over
+    /// the recent candles while takers were net buyers, -1 when it rose
+    /// while they were net sellers, and 0 otherwise.
+    fn compute(&self, klines: &[Kline]) -> Vec<Option<f64>> {
+        let Some(recent) = last_candles(klines, self.recent as usize) else {
+            return vec![None; 5];
+        };
+        let (Some((buy, total)), Some((recent_buy, recent_total))) =
+            (taker_volumes(klines), taker_volumes(recent))
+        else {
+            return vec![None; 5];
+        };
+
+        let cvd_recent = 2.0 * recent_buy - recent_total;
+        let price_change = recent[recent.len() - 1].close - recent[0].open;
+        let divergence = if price_change < 0.0 && cvd_recent > 0.0 {
+            1.0
+        } else if price_change > 0.0 && cvd_recent < 0.0 {
+            -1.0
+        } else {
+            0.0
+        };
+
+        vec![
+            Some(buy / total),
+            Some(2.0 * buy - total),
+            Some(recent_buy / recent_total),
+            Some(cvd_recent),
+            Some(divergence),
+        ]
+    }
+}
+
+// REGISTRY
+
+fn positive(name: &str, param: &str, value: u32) -> Result<u32> {
+    if value == 0 {
+        bail!("Indicator {}: {} must be at least 1", name, param);
+    }
+    Ok(value)
+}
+
+/// Checks that the `short` period of `name` is shorter than the `long` one,
+/// both given with their parameter names.
+fn shorter(name: &str, short: (&str, u32), long: (&str, u32)) -> Result<(u32, u32)> {
+    positive(name, short.0, short.1)?;
+    if short.1 >= long.1 {
+        bail!(
+            "Indicator {}: {} must be shorter than {}",
+            name,
+            short.0,
+            long.0
+        );
+    }
+    Ok((short.1, long.1))
+}
+
+fn build(config: &IndicatorConfig) -> Result<Arc<dyn Indicator>> {
+    Ok(match *config {
+        IndicatorConfig::Rsi { period } => Arc::new(Rsi {
+            period: positive("rsi", "period", period)?,
+        }),
+        IndicatorConfig::Macd { fast, slow, signal } => {
+            let (fast, slow) = shorter("macd", ("fast", fast), ("slow", slow))?;
+            Arc::new(Macd {
+                fast,
+                slow,
+                signal: positive("macd", "signal", signal)?,
+            })
+        }
+        IndicatorConfig::MaCross {
+            average,
+            fast,
+            slow,
+        } => {
+            let (fast, slow) = shorter("ma_cross", ("fast", fast), ("slow", slow))?;
+            Arc::new(MaCross {
+                average,
+                fast,
+                slow,
+            })
+        }
+        IndicatorConfig::Bollinger { period, multiplier } => {
+            if !(multiplier > 0.0 && multiplier.is_finite()) {
+                bail!("Indicator bollinger: multiplier must be above 0");
+            }
+            Arc::new(Bollinger {
+                period: positive("bollinger", "period", period)?,
+                multiplier,
+            })
+        }
+        IndicatorConfig::Atr { period } => Arc::new(Atr {
+            period: positive("atr", "period", period)?,
+        }),
+        IndicatorConfig::RealizedVol { period } => Arc::new(RealizedVol {
+            period: positive("realized_vol", "period", period)?,
+        }),
+        IndicatorConfig::RangeExpansion { recent, long } => {
+            let (recent, long) = shorter("range_expansion", ("recent", recent), ("long", long))?;
+            Arc::new(RangeExpansion { recent, long })
+        }
+        IndicatorConfig::Volume { recent, lookback } => Arc::new(Volume {
+            recent: positive("volume", "recent", recent)?,
+            lookback: positive("volume", "lookback", lookback)?,
+        }),
+        IndicatorConfig::TakerFlow { recent } => Arc::new(TakerFlow {
+            recent: positive("taker_flow", "recent", recent)?,
+        }),
+    })
+}
+
+/// The configured indicators, in configuration order.
+#[derive(Clone)]
+pub struct IndicatorRegistry {
+    indicators: Vec<Arc<dyn Indicator>>,
+}
+
+impl IndicatorRegistry {
+    pub fn from_config(configs: &[IndicatorConfig]) -> Result<Self> {
+        let indicators: Vec<Arc<dyn Indicator>> =
+            configs.iter().map(build).collect::<Result<_>>()?;
+
+        let mut owners: HashMap<&str, &str> = HashMap::new();
+        for indicator in &indicators {
+            for column in indicator.columns() {
+                match owners.insert(column.key, indicator.name()) {
+                    Some(owner) if owner == indicator.name() => {
+                        bail!("Indicator {} is configured more than once", owner)
+                    }
+                    Some(owner) => bail!(
+                        "Indicators {} and {} both fill the {} column",
+                        owner,
+                        indicator.name(),
+                        column.key
+                    ),
+                    None => {}
+                }
+            }
+        }
+        Ok(Self { indicators })
+    }
+
+    pub fn indicators(&self) -> &[Arc<dyn Indicator>] {
+        &self.indicators
+    }
+
+    /// Every column of every indicator, in order.
+    pub fn columns(&self) -> Vec<IndicatorColumn> {
+        self.indicators.iter().flat_map(|i| i.columns()).collect()
+    }
+
+    /// Runs every indicator with enough candles, keeping the finite values.
+    pub fn compute(&self, klines: &[Kline]) -> BTreeMap<String, f64> {
+        let mut values = BTreeMap::new();
+        for indicator in self.indicators.iter() {
+            if klines.len() < indicator.warm_up().max(1) {
+                continue;
+            }
+            for (column, value) in indicator.columns().iter().zip(indicator.compute(klines)) {
+                if let Some(value) = value.filter(|v| v.is_finite()) {
+                    values.insert(column.key.to_string(), value);
+                }
+            }
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: i64 = 60_000;
+
+    fn candles(closes: &[f64], volumes: &[f64]) -> Vec<Kline> {
+        closes
+            .iter()
+            .zip(volumes)
+            .enumerate()
+            .map(|(i, (&close, &quote_volume))| Kline {
+                volume: quote_volume / close,
+                quote_volume,
+                ..Kline::flat(i as i64 * MINUTE, close, MINUTE)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ema_is_seeded_with_the_sma() {
+        let closes = [10.0, 20.0, 30.0, 40.0, 50.0];
+        let ema = MovingAverage::Ema.series(3, &candles(&closes, &[1.0; 5]));
+        assert_eq!(ema[..3], [10.0, 15.0, 20.0]);
+        assert_eq!(ema[3], 0.5 * 40.0 + 0.5 * 20.0);
+        assert_eq!(ema[4], 0.5 * 50.0 + 0.5 * 30.0);
+    }
+
+    fn close_to(value: Option<f64>, expected: f64) -> bool {
+        value.is_some_and(|v| (v - expected).abs() < 1e-9)
+    }
+
+    #[test]
+    fn macd_histogram_uses_the_sma_seeded_ema() {
+        let macd = Macd {
+            fast: 2,
+            slow: 3,
+            signal: 2,
+        };
+
+        // On a straight line every settled EMA lags by (period - 1) / 2, so
+        // the MACD is a constant 0.5 and the histogram is flat.
+        let line: Vec<f64> = (1..=8).map(f64::from).collect();
+        let values = macd.compute(&candles(&line, &[1.0; 8]));
+        assert!(close_to(values[0], 0.0));
+
+        // A jump on the last candle: fast EMA 50/3, slow EMA 15, MACD 5/3,
+        // signal 2/3 * 5/3 over a settled 0, histogram 5/9.
+        let jump = [10.0, 10.0, 10.0, 10.0, 20.0];
+        let values = macd.compute(&candles(&jump, &[1.0; 5]));
+        assert!(close_to(values[0], 5.0 / 9.0));
+        assert!(close_to(values[1], 5.0 / 9.0 / 20.0 * 100.0));
+    }
+
+    #[test]
+    fn bollinger_reports_percent_b_and_bandwidth() {
+        let bollinger = Bollinger {
+            period: 5,
+            multiplier: 2.0,
+        };
+        // Mean 3 and population standard deviation √2, so the bands are
+        // 3 ± 2√2.
+        let values = bollinger.compute(&candles(&[1.0, 2.0, 3.0, 4.0, 5.0], &[1.0; 5]));
+        let half_width = 2.0 * 2f64.sqrt();
+        assert!(close_to(
+            values[0],
+            (5.0 - (3.0 - half_width)) / (2.0 * half_width)
+        ));
+        assert!(close_to(values[1], 2.0 * half_width / 3.0 * 100.0));
+
+        // A last close at the mean sits halfway between the bands.
+        let values = bollinger.compute(&candles(&[1.0, 5.0, 2.0, 4.0, 3.0], &[1.0; 5]));
+        assert!(close_to(values[0], 0.5));
+    }
+
+    #[test]
+    fn bars_since_cross_counts_from_the_crossing_candle() {
+        // Flat for 30 candles, then rising for 10: the fast EMA crosses above
+        // on the first rising candle.
+        let closes: Vec<f64> = (0..40).map(|i| 100.0 + (i - 29).max(0) as f64).collect();
+        let values = MaCross {
+            average: MovingAverage::Ema,
+            fast: 9,
+            slow: 21,
+        }
+        .compute(&candles(&closes, &vec![1.0; closes.len()]));
+        assert_eq!(values[0], Some(9.0));
+        assert!(values[1].unwrap() > 0.0);
+    }
+
+    /// Candles closing at `close`, each ranging `range` either side of it.
+    fn ranging(close: f64, ranges: &[f64]) -> Vec<Kline> {
+        ranges
+            .iter()
+            .enumerate()
+            .map(|(i, &range)| Kline {
+                high: close + range,
+                low: close - range,
+                ..Kline::flat(i as i64 * MINUTE, close, MINUTE)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn atr_of_a_constant_range() {
+        let values = Atr { period: 14 }.compute(&ranging(50.0, &[1.0; 30]));
+        assert!(close_to(values[0], 2.0));
+        assert!(close_to(values[1], 4.0));
+    }
+
+    #[test]
+    fn realized_vol_of_known_returns() {
+        let vol = RealizedVol { period: 10 };
+        let minutes_per_year = 365.0 * 24.0 * 60.0;
+
+        // Constant log returns do not vary at all.
+        let growing: Vec<f64> = (0..11).map(|i| 100.0 * (0.01 * i as f64).exp()).collect();
+        assert!(close_to(
+            vol.compute(&candles(&growing, &[1.0; 11]))[0],
+            0.0
+        ));
+
+        // Returns alternating ±r have a sample standard deviation of
+        // r·√(n / (n - 1)) around their zero mean.
+        let r = 0.02f64;
+        let zigzag: Vec<f64> = (0..11)
+            .map(|i| 100.0 * (r * (i % 2) as f64).exp())
+            .collect();
+        let expected = r * (10.0f64 / 9.0).sqrt() * f64::sqrt(minutes_per_year) * 100.0;
+        let values = vol.compute(&candles(&zigzag, &[1.0; 11]));
+        assert!((values[0].unwrap() - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn range_expansion_rises_with_a_widening_range() {
+        let expansion = RangeExpansion {
+            recent: 14,
+            long: 100,
+        };
+        let steady = ranging(50.0, &[1.0; 120]);
+        assert!(close_to(expansion.compute(&steady)[0], 1.0));
+
+        let mut ranges = vec![1.0; 106];
+        ranges.extend([3.0; 14]);
+        assert!(expansion.compute(&ranging(50.0, &ranges))[0].unwrap() > 1.5);
+    }
+
+    /// Candles of 100 quote volume each, given as (open, close, taker buys).
+    fn taker_candles(candles: &[(f64, f64, f64)]) -> Vec<Kline> {
+        candles
+            .iter()
+            .enumerate()
+            .map(|(i, &(open, close, buy))| Kline {
+                open,
+                quote_volume: 100.0,
+                taker_buy_quote_volume: Some(buy),
+                ..Kline::flat(i as i64 * MINUTE, close, MINUTE)
+            })
+            .collect()
+    }
+
+    const TAKER_FLOW: TakerFlow = TakerFlow { recent: 2 };
+
+    #[test]
+    fn taker_buying_into_a_falling_price_diverges_up() {
+        let klines = taker_candles(&[
+            (100.0, 100.0, 50.0),
+            (100.0, 100.0, 50.0),
+            (100.0, 98.0, 70.0),
+            (98.0, 95.0, 60.0),
+        ]);
+        let values = TAKER_FLOW.compute(&klines);
+        assert_eq!(
+            values,
+            [Some(0.575), Some(60.0), Some(0.65), Some(60.0), Some(1.0)]
+        );
+    }
+
+    #[test]
+    fn taker_selling_into_a_rising_price_diverges_down() {
+        let klines = taker_candles(&[
+            (100.0, 100.0, 50.0),
+            (100.0, 100.0, 50.0),
+            (100.0, 102.0, 30.0),
+            (102.0, 105.0, 40.0),
+        ]);
+        let values = TAKER_FLOW.compute(&klines);
+        assert_eq!(
+            values,
+            [
+                Some(0.425),
+                Some(-60.0),
+                Some(0.35),
+                Some(-60.0),
+                Some(-1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn taker_flow_agreeing_with_price_does_not_diverge() {
+        let klines = taker_candles(&[(100.0, 102.0, 60.0), (102.0, 105.0, 70.0)]);
+        assert_eq!(TAKER_FLOW.compute(&klines)[4], Some(0.0));
+    }
+
+    #[test]
+    fn taker_flow_is_unset_without_taker_volume() {
+        // Venues like Bybit and OKX report no taker volume at all.
+        let untracked = candles(&[1.0, 2.0, 3.0], &[1.0; 3]);
+        assert_eq!(TAKER_FLOW.compute(&untracked), [None; 5]);
+
+        // A single candle without it is enough to unset everything.
+        let mut klines = taker_candles(&[(100.0, 102.0, 60.0), (102.0, 105.0, 70.0)]);
+        klines[0].taker_buy_quote_volume = None;
+        assert_eq!(TAKER_FLOW.compute(&klines), [None; 5]);
+    }
+
+    #[test]
+    fn short_series_leave_the_columns_unset() {
+        let klines = candles(&[1.0, 2.0, 3.0], &[1.0; 3]);
+        let indicators: [&dyn Indicator; 3] = [
+            &RealizedVol { period: 30 },
+            &Volume {
+                recent: 5,
+                lookback: 100,
+            },
+            &TakerFlow { recent: 20 },
+        ];
+        for indicator in indicators {
+            assert!(indicator.compute(&klines).iter().all(Option::is_none));
+        }
+    }
+
+    #[test]
+    fn volume_z_scores_the_last_candle() {
+        // Lookback volumes alternate 90 and 110: mean 100, sample std dev ~10.05.
+        let mut volumes: Vec<f64> = (0..100).map(|i| [90.0, 110.0][i % 2]).collect();
+        volumes.extend([120.0, 120.0, 120.0, 140.0]);
+        let klines = candles(&vec![1.0; volumes.len()], &volumes);
+
+        let values = Volume {
+            recent: 4,
+            lookback: 100,
+        }
+        .compute(&klines);
+        let std_dev = std_dev(&volumes[..100]);
+        assert!((values[0].unwrap() - 1.25).abs() < 1e-9);
+        assert!((values[1].unwrap() - 40.0 / std_dev).abs() < 1e-9);
+        assert_eq!(values[2], None);
     }
-    last_rsi
 }
@@ -1,14 +1,25 @@
+use crate::analysis::Cancelled;
 use crate::find_tickers::ExchangeInfo;
-use crate::storage_utils::{AsyncStorageManager, KlineConfig};
-use anyhow::Result;
-use regex::Regex;
-use reqwest::Client;
-use serde::Serialize;
+use crate::market_data::{self, CandleQuery, FetchError, MarketDataSource};
+use crate::progress::{ProgressEvent, ProgressReporter};
+use crate::retry;
+use crate::storage::Storage;
+use crate::storage_utils::{AppConfig, Exchange, KlineConfig, Market, RetryConfig};
+use anyhow::{Result, anyhow};
+use futures::StreamExt;
+use serde::de::{self, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{Map, Value};
 use std::collections::HashMap;
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::fmt;
+use tokio_util::sync::CancellationToken;
 
-const KLINE_KEYS: &[&str] = &[
+/// Upper bound on concurrent kline requests per market.
+const MAX_IN_FLIGHT: usize = 32;
+
+/// Field names of a candle, in the order of Binance's kline arrays.
+pub const KLINE_KEYS: &[&str] = &[
     "openTime",
     "open",
     "high",
@@ -20,34 +31,381 @@ const KLINE_KEYS: &[&str] = &[
     "numberOfTrades",
     "takerBuyBaseAssetVolume",
     "takerBuyQuoteAssetVolume",
-    "ignore",
 ];
 
-#[derive(Serialize)]
-struct KlineResult {
-    symbol: String,
+/// One candle, decoded once from the venue's response and shared by fetching,
+/// storage and analysis. Only Binance publishes trade counts and taker volumes.
+///
+/// Serialized as an array in `KLINE_KEYS` order, the way Binance sends it.
+/// Objects keyed by `KLINE_KEYS` are read too, as older `klines.json` files
+/// hold those.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Kline {
+    pub open_time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub close_time: i64,
+    pub quote_volume: f64,
+    pub trades: Option<u64>,
+    pub taker_buy_base_volume: Option<f64>,
+    pub taker_buy_quote_volume: Option<f64>,
+}
+
+impl Serialize for Kline {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(KLINE_KEYS.len()))?;
+        seq.serialize_element(&self.open_time)?;
+        seq.serialize_element(&self.open)?;
+        seq.serialize_element(&self.high)?;
+        seq.serialize_element(&self.low)?;
+        seq.serialize_element(&self.close)?;
+        seq.serialize_element(&self.volume)?;
+        seq.serialize_element(&self.close_time)?;
+        seq.serialize_element(&self.quote_volume)?;
+        seq.serialize_element(&self.trades)?;
+        seq.serialize_element(&self.taker_buy_base_volume)?;
+        seq.serialize_element(&self.taker_buy_quote_volume)?;
+        seq.end()
+    }
+}
+
+/// A candle field as venues send it: a number, a decimal string or null.
+struct Field(Option<f64>);
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldVisitor;
+
+        impl Visitor<'_> for FieldVisitor {
+            type Value = Field;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a number, a string holding a number, or null")
+            }
+
+            fn visit_f64<E>(self, v: f64) -> Result<Field, E> {
+                Ok(Field(Some(v)))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Field, E> {
+                Ok(Field(Some(v as f64)))
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Field, E> {
+                Ok(Field(Some(v as f64)))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Field, E> {
+                if v.trim().is_empty() {
+                    return Ok(Field(None));
+                }
+                v.parse().map(|v| Field(Some(v))).map_err(E::custom)
+            }
+
+            fn visit_unit<E>(self) -> Result<Field, E> {
+                Ok(Field(None))
+            }
+
+            fn visit_none<E>(self) -> Result<Field, E> {
+                Ok(Field(None))
+            }
+        }
+
+        deserializer.deserialize_any(FieldVisitor)
+    }
+}
+
+impl Kline {
+    /// Builds a candle from fields in `KLINE_KEYS` order, failing when one of
+    /// the fields every venue publishes is missing.
+    pub fn from_fields<E: de::Error>(fields: [Option<f64>; 11]) -> Result<Self, E> {
+        let required = |i: usize| fields[i].ok_or_else(|| E::missing_field(KLINE_KEYS[i]));
+        Ok(Kline {
+            open_time: required(0)? as i64,
+            open: required(1)?,
+            high: required(2)?,
+            low: required(3)?,
+            close: required(4)?,
+            volume: required(5)?,
+            close_time: required(6)? as i64,
+            quote_volume: required(7)?,
+            trades: fields[8].map(|v| v as u64),
+            taker_buy_base_volume: fields[9],
+            taker_buy_quote_volume: fields[10],
+        })
+    }
+}
+
+#[cfg(test)]
+impl Kline {
+    /// A candle opening, closing and ranging at `close`, with one unit of
+    /// volume and neither trade counts nor taker volumes.
+    pub fn flat(open_time: i64, close: f64, interval_ms: i64) -> Self {
+        Kline {
+            open_time,
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 1.0,
+            close_time: open_time + interval_ms - 1,
+            quote_volume: close,
+            trades: None,
+            taker_buy_base_volume: None,
+            taker_buy_quote_volume: None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Kline {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KlineVisitor;
+
+        impl<'de> Visitor<'de> for KlineVisitor {
+            type Value = Kline;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a kline array or object")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Kline, A::Error> {
+                let mut fields = [None; 11];
+                for field in &mut fields {
+                    match seq.next_element::<Field>()? {
+                        Some(Field(value)) => *field = value,
+                        None => break,
+                    }
+                }
+                // Binance appends an unused `ignore` field.
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                Kline::from_fields(fields)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Kline, A::Error> {
+                let mut fields = [None; 11];
+                while let Some(key) = map.next_key::<String>()? {
+                    match KLINE_KEYS.iter().position(|&k| k == key) {
+                        Some(i) => fields[i] = map.next_value::<Field>()?.0,
+                        None => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+                Kline::from_fields(fields)
+            }
+        }
+
+        deserializer.deserialize_any(KlineVisitor)
+    }
+}
+
+/// Candles of one symbol, as stored in `klines.json`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KlineResult {
+    pub symbol: String,
+    #[serde(default)]
+    pub exchange: Exchange,
+    #[serde(default)]
+    pub market: Market,
+    #[serde(default)]
+    pub interval: String,
     #[serde(rename = "underlyingSubType")]
-    underlying_sub_type: Vec<String>,
-    klines: Vec<Map<String, Value>>,
+    pub underlying_sub_type: Vec<String>,
+    pub klines: Vec<Kline>,
 }
 
-fn calculate_request_weight(limit: u32) -> u32 {
-    match limit {
-        0..=99 => 1,
-        100..=499 => 2,
-        500..=1000 => 5,
-        _ => 10,
+/// A symbol whose candles could not be downloaded, and why.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FetchFailure {
+    pub symbol: String,
+    pub exchange: Exchange,
+    pub market: Market,
+    pub error: FetchError,
+}
+
+/// Candles of one market left over from the previous refresh, by symbol.
+type KlineCache = HashMap<String, Vec<Kline>>;
+
+/// What to download for one symbol: the candles already cached, and how many
+/// newer candles are still missing. Pages stop early once they reach `floor`.
+struct FetchPlan<'a> {
+    symbol_map: &'a Map<String, Value>,
+    cached: Vec<Kline>,
+    count: u32,
+    floor: Option<i64>,
+}
+
+/// Number of candles the configured window holds: `limit`, or every candle
+/// since `start_date` when one is set.
+pub fn window_len(klines_config: &KlineConfig, now_ms: i64) -> Result<u32> {
+    let Some(start_date) = &klines_config.start_date else {
+        return Ok(klines_config.limit);
+    };
+
+    let start_ms = match chrono::DateTime::parse_from_rfc3339(start_date) {
+        Ok(dt) => dt.timestamp_millis(),
+        Err(_) => chrono::NaiveDate::parse_from_str(start_date, "%Y-%m-%d")
+            .map_err(|_| anyhow!("Invalid klines.start_date: {}", start_date))?
+            .and_time(chrono::NaiveTime::MIN)
+            .and_utc()
+            .timestamp_millis(),
+    };
+    let interval_ms = market_data::interval_millis(&klines_config.interval)
+        .ok_or_else(|| anyhow!("Unsupported interval: {}", klines_config.interval))?;
+
+    Ok(((now_ms - start_ms).max(0) / interval_ms + 1) as u32)
+}
+
+/// Plans an incremental download on top of the cached candles. The last cached
+/// candle may have been incomplete when it was stored, so it is always fetched
+/// again. Falls back to a full download when nothing usable is cached, or
+/// when the cached series would stay shorter than the window, e.g. after
+/// `limit` was raised or `start_date` moved earlier.
+fn plan_fetch<'a>(
+    symbol_map: &'a Map<String, Value>,
+    mut cached: Vec<Kline>,
+    klines_config: &KlineConfig,
+    window: u32,
+    now_ms: i64,
+) -> FetchPlan<'a> {
+    let full = || FetchPlan {
+        symbol_map,
+        cached: Vec::new(),
+        count: window,
+        floor: None,
+    };
+
+    let Some(interval_ms) = market_data::interval_millis(&klines_config.interval) else {
+        return full();
+    };
+    let Some(resume_from) = cached.pop().map(|k| k.open_time) else {
+        return full();
+    };
+
+    let missing = ((now_ms - resume_from).max(0) / interval_ms + 1) as u32;
+    if missing >= window || cached.len() as u32 + missing < window {
+        return full();
+    }
+
+    FetchPlan {
+        symbol_map,
+        cached,
+        count: missing + 1,
+        floor: Some(resume_from),
     }
 }
 
+/// Downloads the latest `count` candles, paging backwards with `end_time`
+/// when they do not fit in one request. Stops early at `floor`. Pages are
+/// merged into a single de-duplicated series, oldest first. Every page spends
+/// its own weight from the limiter of its endpoint, retries included.
+async fn fetch_page(
+    source: &dyn MarketDataSource,
+    query: &CandleQuery<'_>,
+    retry_config: &RetryConfig,
+    progress: &ProgressReporter,
+) -> Result<Vec<Kline>, FetchError> {
+    retry::with_retry(
+        retry_config,
+        |e| retry::is_retryable(e, retry_config),
+        || async {
+            let limiter = source.rate_limiter_for(query);
+            if let Some(wait) = limiter.paused_for() {
+                progress.send(ProgressEvent::RateLimited { wait });
+            }
+            limiter.acquire(source.request_weight(query.limit)).await;
+            source.fetch_candles(query).await
+        },
+    )
+    .await
+}
+
+async fn fetch_history(
+    source: &dyn MarketDataSource,
+    symbol: &str,
+    interval: &str,
+    count: u32,
+    floor: Option<i64>,
+    retry_config: &RetryConfig,
+    progress: &ProgressReporter,
+) -> Result<Vec<Kline>, FetchError> {
+    // Small incremental updates fit in one request starting at the floor.
+    if let Some(floor) = floor
+        && count <= source.max_limit()
+    {
+        let query = CandleQuery {
+            symbol,
+            interval,
+            limit: count,
+            start_time: Some(floor),
+            end_time: None,
+        };
+        return fetch_page(source, &query, retry_config, progress).await;
+    }
+
+    let mut merged: Vec<Kline> = Vec::new();
+    let mut end_time = None;
+
+    while (merged.len() as u32) < count {
+        let max = match end_time {
+            Some(_) => source.max_history_limit(),
+            None => source.max_limit(),
+        };
+        let query = CandleQuery {
+            symbol,
+            interval,
+            limit: (count - merged.len() as u32).min(max),
+            start_time: None,
+            end_time,
+        };
+        let oldest_known = merged.first().map(|k| k.open_time);
+        let mut page: Vec<Kline> = fetch_page(source, &query, retry_config, progress)
+            .await?
+            .into_iter()
+            .filter(|k| {
+                oldest_known.is_none_or(|oldest| k.open_time < oldest)
+                    && floor.is_none_or(|floor| k.open_time >= floor)
+            })
+            .collect();
+
+        // An empty page means the listing does not go back any further.
+        let Some(page_oldest) = page.first().map(|k| k.open_time) else {
+            break;
+        };
+        page.append(&mut merged);
+        merged = page;
+
+        if floor.is_some_and(|floor| page_oldest <= floor) {
+            break;
+        }
+        end_time = Some(page_oldest - 1);
+    }
+
+    Ok(merged)
+}
+
 async fn fetch_kline(
-    client: &Client,
-    symbol_map: &Map<String, Value>,
-    params: &[(&str, String)],
-) -> Option<KlineResult> {
-    let symbol = match symbol_map.get("symbol").and_then(|v| v.as_str()) {
-        Some(s) => s.to_string(),
-        None => return None,
+    source: &dyn MarketDataSource,
+    plan: FetchPlan<'_>,
+    klines_config: &KlineConfig,
+    retry_config: &RetryConfig,
+    window: u32,
+    progress: &ProgressReporter,
+) -> Result<KlineResult, FetchFailure> {
+    let symbol_map = plan.symbol_map;
+    let symbol = symbol_map
+        .get("symbol")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let failure = |error| FetchFailure {
+        symbol: symbol.clone(),
+        exchange: source.exchange(),
+        market: source.market(),
+        error,
     };
 
     let sub_types: Vec<String> = match symbol_map
@@ -61,68 +419,36 @@ async fn fetch_kline(
         None => Vec::new(),
     };
 
-    let url = "https://fapi.binance.com/fapi/v1/klines";
-    let mut query = params.to_vec();
-    query.push(("symbol", symbol.clone()));
-
-    let resp = client.get(url).query(&query).send().await;
-
-    match resp {
-        Ok(response) => {
-            let status = response.status();
-
-            if status == 418 || status == 429 {
-                if let Ok(text) = response.text().await
-                    && text.contains("-1003")
-                {
-                    let re = Regex::new(r"until\s+(\d+)").unwrap();
-                    if let Some(caps) = re.captures(&text)
-                        && let Some(ts_match) = caps.get(1)
-                        && let Ok(ban_until) = ts_match.as_str().parse::<u64>()
-                    {
-                        let now = SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis() as u64;
-                        if ban_until > now {
-                            let wait_ms = ban_until - now;
-                            let wait_sec = (wait_ms as f64 / 1000.0) + 5.0;
-                            tokio::time::sleep(Duration::from_secs_f64(wait_sec)).await;
-                            return None;
-                        }
-                    }
-                }
-                return None;
-            }
+    let fetched = fetch_history(
+        source,
+        &symbol,
+        &klines_config.interval,
+        plan.count,
+        plan.floor,
+        retry_config,
+        progress,
+    )
+    .await
+    .map_err(failure)?;
 
-            if !status.is_success() {
-                return None;
-            }
-
-            match response.json::<Vec<Vec<Value>>>().await {
-                Ok(raw_klines) => {
-                    let klines_as_dicts = raw_klines
-                        .into_iter()
-                        .map(|k| {
-                            KLINE_KEYS
-                                .iter()
-                                .zip(k.into_iter())
-                                .map(|(&key, val)| (key.to_string(), val))
-                                .collect()
-                        })
-                        .collect();
-
-                    Some(KlineResult {
-                        symbol,
-                        underlying_sub_type: sub_types,
-                        klines: klines_as_dicts,
-                    })
-                }
-                Err(_) => None,
-            }
-        }
-        Err(_) => None,
+    // Append only candles newer than the cached ones, then trim to the window.
+    let mut klines = plan.cached;
+    let newest_cached = klines.last().map_or(i64::MIN, |k| k.open_time);
+    klines.extend(fetched.into_iter().filter(|k| k.open_time > newest_cached));
+    let excess = klines.len().saturating_sub(window as usize);
+    klines.drain(..excess);
+    if klines.is_empty() {
+        return Err(failure(FetchError::EmptyResponse));
     }
+
+    Ok(KlineResult {
+        symbol,
+        exchange: source.exchange(),
+        market: source.market(),
+        interval: klines_config.interval.clone(),
+        underlying_sub_type: sub_types,
+        klines,
+    })
 }
 
 fn matches_filters(symbol: &Map<String, Value>, filters: &HashMap<String, String>) -> bool {
@@ -136,55 +462,311 @@ fn matches_filters(symbol: &Map<String, Value>, filters: &HashMap<String, String
         })
 }
 
-pub async fn run(klines_config: &KlineConfig, filters: &HashMap<String, String>) -> Result<()> {
-    let storage = AsyncStorageManager::new_relative("storage").await?;
-    let exchange_info: ExchangeInfo = storage.load("exchange_info").await?;
+async fn fetch_market(
+    storage: &dyn Storage,
+    source: &dyn MarketDataSource,
+    klines_config: &KlineConfig,
+    retry_config: &RetryConfig,
+    filters: &HashMap<String, String>,
+    mut cache: KlineCache,
+    progress: &ProgressReporter,
+) -> Result<(Vec<KlineResult>, Vec<FetchFailure>)> {
+    let exchange_info: ExchangeInfo = storage
+        .load_exchange_info(&market_data::exchange_info_key(source))
+        .await?;
+    let api_limit_total = source.weight_limit(&exchange_info);
+    source
+        .rate_limiter()
+        .set_capacity((api_limit_total as f64 * 0.90) as u32);
 
     let symbols_to_fetch: Vec<Map<String, Value>> = exchange_info
         .symbols
         .into_iter()
-        .filter(|s| matches_filters(s, filters))
+        .filter(|s| s.get("symbol").is_some_and(Value::is_string) && matches_filters(s, filters))
         .collect();
 
-    let client = Client::builder().pool_max_idle_per_host(50).build()?;
-    let limit_str = klines_config.limit.to_string();
-    let kline_params = vec![
-        ("interval", klines_config.interval.clone()),
-        ("limit", limit_str),
-    ];
-    let weight_per_req = calculate_request_weight(klines_config.limit);
+    let now_ms = chrono::Utc::now().timestamp_millis();
+    let window = window_len(klines_config, now_ms)?;
+    let plans = symbols_to_fetch.iter().map(|s| {
+        let cached = s
+            .get("symbol")
+            .and_then(Value::as_str)
+            .and_then(|symbol| cache.remove(symbol))
+            .unwrap_or_default();
+        plan_fetch(s, cached, klines_config, window, now_ms)
+    });
 
-    let api_limit_total = exchange_info
-        .rate_limits
-        .iter()
-        .find(|r| r.limit_type == "REQUEST_WEIGHT" && r.interval == "MINUTE")
-        .map(|r| r.limit)
-        .unwrap_or(2400);
+    // The limiter paces the requests, this only bounds how many are open at once.
+    let tasks: Vec<_> = plans
+        .map(|plan| async move {
+            let result =
+                fetch_kline(source, plan, klines_config, retry_config, window, progress).await;
+            progress.send(ProgressEvent::SymbolFetched {
+                failed: result.is_err(),
+            });
+            result
+        })
+        .collect();
+    progress.send(ProgressEvent::SymbolsPlanned { count: tasks.len() });
+    let results: Vec<Result<KlineResult, FetchFailure>> = futures::stream::iter(tasks)
+        .buffered(MAX_IN_FLIGHT)
+        .collect()
+        .await;
+
+    let mut market_results = Vec::new();
+    let mut failures = Vec::new();
+    for result in results {
+        match result {
+            Ok(kline_result) => market_results.push(kline_result),
+            Err(failure) => failures.push(failure),
+        }
+    }
+    Ok((market_results, failures))
+}
 
-    let safe_capacity = (api_limit_total as f64 * 0.90) as u32;
-    let batch_size = std::cmp::max(1, safe_capacity / weight_per_req) as usize;
+/// Splits the stored candles of the configured interval by market, skipping
+/// those too old to fall in the window.
+async fn load_cache(
+    storage: &dyn Storage,
+    klines_config: &KlineConfig,
+) -> HashMap<(Exchange, Market), KlineCache> {
+    let now_ms = chrono::Utc::now().timestamp_millis();
+    let since = match (
+        window_len(klines_config, now_ms),
+        market_data::interval_millis(&klines_config.interval),
+    ) {
+        (Ok(window), Some(interval_ms)) => now_ms - window as i64 * interval_ms,
+        _ => 0,
+    };
+    let previous = storage
+        .load_candles(&klines_config.interval, since)
+        .await
+        .unwrap_or_default();
 
-    let mut all_results = Vec::new();
+    let mut cache: HashMap<(Exchange, Market), KlineCache> = HashMap::new();
+    for entry in previous {
+        cache
+            .entry((entry.exchange, entry.market))
+            .or_default()
+            .insert(entry.symbol, entry.klines);
+    }
+    cache
+}
 
-    for (i, batch) in symbols_to_fetch.chunks(batch_size).enumerate() {
-        let start_time = Instant::now();
+/// How many symbols a kline download fetched and how many it gave up on.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FetchSummary {
+    pub fetched: usize,
+    pub failed: usize,
+}
 
-        let tasks: Vec<_> = batch
-            .iter()
-            .map(|s| fetch_kline(&client, s, &kline_params))
-            .collect();
-        let results = futures::future::join_all(tasks).await;
-        all_results.extend(results.into_iter().flatten());
-
-        if i * batch_size + batch.len() < symbols_to_fetch.len() {
-            let elapsed = start_time.elapsed();
-            if elapsed.as_secs() < 60 {
-                let wait = Duration::from_secs(62) - elapsed;
-                tokio::time::sleep(wait).await;
-            }
+/// Downloads candles for every selected market, reusing the candles already
+/// in storage. Each market has its own weight budget, so they are fetched
+/// concurrently. Symbols that could not be downloaded are recorded next to the
+/// candles. When `cancel` fires, the downloads are dropped and the stored
+/// candles are left untouched.
+pub async fn run(
+    storage: &dyn Storage,
+    sources: &[Box<dyn MarketDataSource>],
+    app_config: &AppConfig,
+    progress: &ProgressReporter,
+    cancel: &CancellationToken,
+) -> Result<FetchSummary> {
+    let mut cache = load_cache(storage, &app_config.klines).await;
+
+    let tasks = sources.iter().map(|source| {
+        fetch_market(
+            storage,
+            source.as_ref(),
+            &app_config.klines,
+            &app_config.retry,
+            app_config.filters_for(source.exchange(), source.market()),
+            cache
+                .remove(&(source.exchange(), source.market()))
+                .unwrap_or_default(),
+            progress,
+        )
+    });
+    let mut all_results: Vec<KlineResult> = Vec::new();
+    let mut all_failures: Vec<FetchFailure> = Vec::new();
+    let markets = tokio::select! {
+        markets = futures::future::try_join_all(tasks) => markets?,
+        _ = cancel.cancelled() => return Err(Cancelled.into()),
+    };
+    for (results, failures) in markets {
+        all_results.extend(results);
+        all_failures.extend(failures);
+    }
+
+    storage.save_klines(&all_results).await?;
+    storage.save_fetch_errors(&all_failures).await?;
+    Ok(FetchSummary {
+        fetched: all_results.len(),
+        failed: all_failures.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limit::RateLimiter;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    const MINUTE: i64 = 60_000;
+
+    fn config(limit: u32) -> KlineConfig {
+        KlineConfig {
+            limit,
+            interval: "1m".to_string(),
+            start_date: None,
         }
     }
 
-    storage.save("klines", &all_results).await?;
-    Ok(())
+    /// `count` one-minute candles, the last one opening at `last_open`.
+    fn cached(count: i64, last_open: i64) -> Vec<Kline> {
+        (0..count)
+            .map(|i| Kline::flat(last_open - (count - 1 - i) * MINUTE, 1.0, MINUTE))
+            .collect()
+    }
+
+    #[test]
+    fn full_cache_only_fetches_the_newest_candles() {
+        let symbol = Map::new();
+        let now = 1_000 * MINUTE;
+        let plan = plan_fetch(
+            &symbol,
+            cached(100, now - 2 * MINUTE),
+            &config(100),
+            100,
+            now,
+        );
+        assert_eq!(plan.cached.len(), 99);
+        assert_eq!(plan.count, 4);
+        assert_eq!(plan.floor, Some(now - 2 * MINUTE));
+    }
+
+    #[test]
+    fn short_cache_is_backfilled_with_a_full_fetch() {
+        let symbol = Map::new();
+        let now = 1_000 * MINUTE;
+        // The window grew from 100 to 500 candles since the cache was stored.
+        let plan = plan_fetch(
+            &symbol,
+            cached(100, now - 2 * MINUTE),
+            &config(500),
+            500,
+            now,
+        );
+        assert!(plan.cached.is_empty());
+        assert_eq!(plan.count, 500);
+        assert_eq!(plan.floor, None);
+    }
+
+    #[test]
+    fn stale_cache_is_replaced_with_a_full_fetch() {
+        let symbol = Map::new();
+        let now = 1_000 * MINUTE;
+        let plan = plan_fetch(
+            &symbol,
+            cached(100, now - 200 * MINUTE),
+            &config(100),
+            100,
+            now,
+        );
+        assert!(plan.cached.is_empty());
+        assert_eq!(plan.count, 100);
+    }
+
+    /// Serves `listed` one-minute candles up to `now`, recording every query,
+    /// with smaller pages when paging back like OKX.
+    struct PagedSource {
+        now: i64,
+        listed: i64,
+        queries: Mutex<Vec<(u32, bool)>>,
+        limiter: RateLimiter,
+    }
+
+    #[async_trait]
+    impl MarketDataSource for PagedSource {
+        fn exchange(&self) -> Exchange {
+            Exchange::Okx
+        }
+
+        fn market(&self) -> Market {
+            Market::UsdM
+        }
+
+        async fn list_symbols(&self) -> Result<ExchangeInfo> {
+            Ok(ExchangeInfo {
+                symbols: Vec::new(),
+                rate_limits: Vec::new(),
+            })
+        }
+
+        async fn fetch_candles(&self, query: &CandleQuery<'_>) -> Result<Vec<Kline>, FetchError> {
+            self.queries
+                .lock()
+                .unwrap()
+                .push((query.limit, query.end_time.is_some()));
+            let newest = query.end_time.map_or(self.now, |end| end - end % MINUTE);
+            let oldest = (newest - (query.limit as i64 - 1) * MINUTE)
+                .max(self.now - (self.listed - 1) * MINUTE);
+            Ok(cached((newest - oldest) / MINUTE + 1, newest))
+        }
+
+        fn max_limit(&self) -> u32 {
+            300
+        }
+
+        fn max_history_limit(&self) -> u32 {
+            100
+        }
+
+        fn request_weight(&self, _limit: u32) -> u32 {
+            1
+        }
+
+        fn weight_limit(&self, _exchange_info: &ExchangeInfo) -> u32 {
+            1_000
+        }
+
+        fn rate_limiter(&self) -> &RateLimiter {
+            &self.limiter
+        }
+    }
+
+    #[tokio::test]
+    async fn history_pages_use_the_history_limit() {
+        let source = PagedSource {
+            now: 1_000 * MINUTE,
+            listed: 1_000,
+            queries: Mutex::new(Vec::new()),
+            limiter: RateLimiter::new(1_000, Duration::from_secs(1)),
+        };
+        let klines = fetch_history(
+            &source,
+            "BTC-USDT-SWAP",
+            "1m",
+            550,
+            None,
+            &RetryConfig::default(),
+            &ProgressReporter::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(klines.len(), 550);
+        assert!(
+            klines
+                .windows(2)
+                .all(|w| w[1].open_time - w[0].open_time == MINUTE)
+        );
+        assert_eq!(klines.last().unwrap().open_time, source.now);
+        assert_eq!(
+            *source.queries.lock().unwrap(),
+            [(300, false), (100, true), (100, true), (50, true)]
+        );
+    }
 }
@@ -1,20 +1,31 @@
 mod analysis;
+mod bench;
+mod binance;
+mod bybit;
+mod cli;
+mod comfy_table;
 mod cumulative_price_change;
+mod export;
 mod find_tickers;
 mod indicators;
 mod klines;
+mod market_data;
+mod okx;
+mod progress;
+mod rate_limit;
+mod retry;
+mod snapshots;
+mod sqlite_storage;
+mod storage;
 mod storage_utils;
+mod stream;
 mod tui;
 
+use clap::Parser;
+use std::process::ExitCode;
+
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    // Directly launch the TUI.
-    if let Err(e) = tui::run_tui().await {
-        // If the TUI exits with an error, print it to stderr.
-        // Benign "Quit" errors are suppressed.
-        if !e.to_string().contains("Quit") {
-            eprintln!("TUI Error: {}", e);
-        }
-    }
-    Ok(())
+async fn main() -> ExitCode {
+    // Without a subcommand this launches the TUI.
+    cli::run(cli::Cli::parse()).await
 }
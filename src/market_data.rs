@@ -0,0 +1,181 @@
+//! Venue-agnostic market data access.
+//!
+//! Every exchange the screener can talk to implements [`MarketDataSource`].
+//! The rest of the pipeline only sees the normalized shapes returned here, so
+//! adding a venue does not require touching `find_tickers`, `klines` or the
+//! analysis code.
+
+use crate::binance::Binance;
+use crate::bybit::Bybit;
+use crate::find_tickers::ExchangeInfo;
+use crate::klines::Kline;
+use crate::okx::Okx;
+use crate::rate_limit::RateLimiter;
+use crate::storage_utils::{AppConfig, Exchange, Market};
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A single candle request. Without `start_time` or `end_time` the latest
+/// `limit` candles are returned; with `start_time` the first `limit` candles
+/// from that time on, and with `end_time` the last `limit` candles up to it.
+pub struct CandleQuery<'a> {
+    pub symbol: &'a str,
+    pub interval: &'a str,
+    pub limit: u32,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+}
+
+/// Why a candle request failed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FetchError {
+    /// The request never got a response.
+    Network { message: String },
+    /// The venue answered with a non-success status.
+    HttpStatus { status: u16 },
+    /// The venue reported an error code in an otherwise successful response.
+    Api { code: String, message: String },
+    /// Too many requests; the venue accepts them again at `until` (ms).
+    RateLimited { until: i64 },
+    /// The IP is banned until `until` (ms).
+    Banned { until: i64 },
+    /// The response body could not be decoded.
+    Decode { message: String },
+    /// The venue returned no candles.
+    EmptyResponse,
+    /// The request cannot be expressed for this venue, e.g. an unsupported interval.
+    InvalidRequest { message: String },
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = |ms: i64| {
+            DateTime::from_timestamp_millis(ms)
+                .map(|dt| dt.format("%H:%M:%S").to_string())
+                .unwrap_or_else(|| ms.to_string())
+        };
+        match self {
+            FetchError::Network { message } => write!(f, "network error: {}", message),
+            FetchError::HttpStatus { status } => write!(f, "HTTP status {}", status),
+            FetchError::Api { code, message } => write!(f, "API error {}: {}", code, message),
+            FetchError::RateLimited { until } => write!(f, "rate limited until {}", time(*until)),
+            FetchError::Banned { until } => write!(f, "IP banned until {}", time(*until)),
+            FetchError::Decode { message } => write!(f, "could not decode response: {}", message),
+            FetchError::EmptyResponse => write!(f, "empty response"),
+            FetchError::InvalidRequest { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            FetchError::Decode {
+                message: e.to_string(),
+            }
+        } else {
+            FetchError::Network {
+                message: e.to_string(),
+            }
+        }
+    }
+}
+
+impl From<serde_json::Error> for FetchError {
+    fn from(e: serde_json::Error) -> Self {
+        FetchError::Decode {
+            message: e.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait MarketDataSource: Send + Sync {
+    /// The venue this source talks to.
+    fn exchange(&self) -> Exchange;
+
+    /// The market this source serves.
+    fn market(&self) -> Market;
+
+    /// Lists every symbol the venue offers, together with its rate limits.
+    async fn list_symbols(&self) -> Result<ExchangeInfo>;
+
+    /// Fetches the candles described by `query`, oldest first.
+    async fn fetch_candles(&self, query: &CandleQuery<'_>) -> Result<Vec<Kline>, FetchError>;
+
+    /// Largest number of candles a single request may ask for.
+    fn max_limit(&self) -> u32;
+
+    /// Largest number of candles a request paging back with `end_time` may
+    /// ask for, for venues serving older candles from a separate endpoint.
+    fn max_history_limit(&self) -> u32 {
+        self.max_limit()
+    }
+
+    /// Weight charged for a single candle request of `limit` candles.
+    fn request_weight(&self, limit: u32) -> u32;
+
+    /// Request weight budget per rate limit window.
+    fn weight_limit(&self, exchange_info: &ExchangeInfo) -> u32;
+
+    /// Limiter every request to this source goes through.
+    fn rate_limiter(&self) -> &RateLimiter;
+
+    /// Limiter the request for `query` goes through, for venues pacing their
+    /// history endpoint separately.
+    fn rate_limiter_for(&self, _query: &CandleQuery<'_>) -> &RateLimiter {
+        self.rate_limiter()
+    }
+}
+
+/// Builds one market data source for every market selected in the application config.
+pub fn from_config(config: &AppConfig) -> Result<Vec<Box<dyn MarketDataSource>>> {
+    config
+        .market
+        .iter()
+        .map(|&market| -> Result<Box<dyn MarketDataSource>> {
+            Ok(match (config.exchange, market) {
+                (Exchange::Binance, _) => Box::new(Binance::new(market)?),
+                (Exchange::Bybit, Market::UsdM) => Box::new(Bybit::new()?),
+                (Exchange::Okx, Market::UsdM) => Box::new(Okx::new()?),
+                (exchange, market) => bail!(
+                    "{} does not offer the {} market",
+                    exchange.label(),
+                    market.id()
+                ),
+            })
+        })
+        .collect()
+}
+
+/// Storage key under which a source's exchange info is kept.
+pub fn exchange_info_key(source: &dyn MarketDataSource) -> String {
+    format!(
+        "exchange_info_{}_{}",
+        source.exchange().id(),
+        source.market().id()
+    )
+}
+
+/// Converts a Binance style interval such as `15m` or `4h` into milliseconds.
+pub fn interval_millis(interval: &str) -> Option<i64> {
+    let split = interval.find(|c: char| !c.is_ascii_digit())?;
+    let (count, unit) = interval.split_at(split);
+    let count: i64 = count.parse().ok()?;
+    let unit_ms = match unit {
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        "w" => 604_800_000,
+        "M" => 2_592_000_000,
+        _ => return None,
+    };
+    Some(count * unit_ms)
+}
@@ -0,0 +1,410 @@
+//! OKX linear perpetual (USDT-margined SWAP) implementation of [`MarketDataSource`].
+//!
+//! Like the Bybit adapter, parsing works on raw response bodies so it can be
+//! checked against recorded responses offline.
+
+use crate::find_tickers::ExchangeInfo;
+use crate::klines::Kline;
+use crate::market_data::{self, CandleQuery, FetchError, MarketDataSource};
+use crate::rate_limit::RateLimiter;
+use crate::storage_utils::{Exchange, Market};
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::time::Duration;
+
+const BASE_URL: &str = "https://www.okx.com";
+
+/// OKX allows 40 candle requests per IP in any 2 second window, and 20 more
+/// to the history endpoint.
+const REQUESTS_PER_WINDOW: u32 = 40;
+const HISTORY_REQUESTS_PER_WINDOW: u32 = 20;
+const RATE_WINDOW: Duration = Duration::from_secs(2);
+const MAX_LIMIT: u32 = 300;
+const MAX_HISTORY_LIMIT: u32 = 100;
+const RATE_LIMITED_CODE: &str = "50011";
+
+// DATA
+
+#[derive(Deserialize)]
+struct Envelope<T> {
+    code: String,
+    #[serde(default)]
+    msg: String,
+    data: Option<T>,
+}
+
+impl<T> Envelope<T> {
+    fn into_data(self) -> Result<T> {
+        if self.code != "0" {
+            bail!("OKX error {}: {}", self.code, self.msg);
+        }
+        self.data.ok_or_else(|| anyhow!("OKX response has no data"))
+    }
+}
+
+// PARSING
+
+/// Parses `/api/v5/public/instruments` into normalized symbols, keeping only
+/// linear contracts.
+pub fn parse_instruments(body: &str) -> Result<Vec<Map<String, Value>>> {
+    let instruments: Vec<Map<String, Value>> =
+        serde_json::from_str::<Envelope<_>>(body)?.into_data()?;
+    Ok(instruments
+        .into_iter()
+        .filter(|i| i.get("ctType").and_then(Value::as_str) == Some("linear"))
+        .map(normalize_instrument)
+        .collect())
+}
+
+/// Maps OKX's instrument fields onto the Binance names the filters use.
+fn normalize_instrument(mut instrument: Map<String, Value>) -> Map<String, Value> {
+    let status = match instrument.get("state").and_then(Value::as_str) {
+        Some("live") => "TRADING".to_string(),
+        Some(other) => other.to_uppercase(),
+        None => String::new(),
+    };
+    let contract_type = match instrument.get("instType").and_then(Value::as_str) {
+        Some("SWAP") => "PERPETUAL",
+        Some("FUTURES") => "DELIVERY",
+        _ => "",
+    };
+
+    if let Some(inst_id) = instrument.get("instId").cloned() {
+        instrument.insert("symbol".to_string(), inst_id);
+    }
+    instrument.insert("status".to_string(), Value::from(status));
+    instrument.insert("contractType".to_string(), Value::from(contract_type));
+    for (from, to) in [("ctValCcy", "baseAsset"), ("settleCcy", "quoteAsset")] {
+        if let Some(value) = instrument.get(from).cloned() {
+            instrument.insert(to.to_string(), value);
+        }
+    }
+    instrument
+        .entry("underlyingSubType")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    instrument
+}
+
+/// Parses `/api/v5/market/candles` into candles, oldest first.
+pub fn parse_klines(body: &str, interval_ms: i64) -> Result<Vec<Kline>> {
+    let rows: Vec<Vec<String>> = serde_json::from_str::<Envelope<_>>(body)?.into_data()?;
+
+    // OKX lists candles newest first as
+    // [ts, open, high, low, close, vol, volCcy, volCcyQuote, confirm].
+    rows.into_iter()
+        .rev()
+        .map(|row| {
+            let [
+                ts,
+                open,
+                high,
+                low,
+                close,
+                _vol,
+                vol_ccy,
+                vol_ccy_quote,
+                _confirm,
+            ] = <[String; 9]>::try_from(row)
+                .map_err(|row| anyhow!("Unexpected OKX candle row: {:?}", row))?;
+            let open_time: i64 = ts.parse()?;
+            Ok(Kline {
+                open_time,
+                open: open.parse()?,
+                high: high.parse()?,
+                low: low.parse()?,
+                close: close.parse()?,
+                volume: vol_ccy.parse()?,
+                close_time: open_time + interval_ms - 1,
+                quote_volume: vol_ccy_quote.parse()?,
+                trades: None,
+                taker_buy_base_volume: None,
+                taker_buy_quote_volume: None,
+            })
+        })
+        .collect()
+}
+
+/// Converts a Binance style interval into an OKX bar, using the UTC aligned
+/// variants for six hours and longer.
+fn okx_bar(interval: &str) -> Option<&'static str> {
+    Some(match interval {
+        "1m" => "1m",
+        "3m" => "3m",
+        "5m" => "5m",
+        "15m" => "15m",
+        "30m" => "30m",
+        "1h" => "1H",
+        "2h" => "2H",
+        "4h" => "4H",
+        "6h" => "6Hutc",
+        "12h" => "12Hutc",
+        "1d" => "1Dutc",
+        "1w" => "1Wutc",
+        "1M" => "1Mutc",
+        _ => return None,
+    })
+}
+
+// SOURCE
+
+pub struct Okx {
+    client: Client,
+    base_url: String,
+    limiter: RateLimiter,
+    history_limiter: RateLimiter,
+}
+
+impl Okx {
+    pub fn new() -> Result<Self> {
+        Self::with_base_url(BASE_URL)
+    }
+
+    /// Points the source at another host, e.g. a local stand-in for the API.
+    pub fn with_base_url(base_url: &str) -> Result<Self> {
+        let client = Client::builder().pool_max_idle_per_host(50).build()?;
+        Ok(Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            limiter: RateLimiter::new(REQUESTS_PER_WINDOW, RATE_WINDOW),
+            history_limiter: RateLimiter::new(HISTORY_REQUESTS_PER_WINDOW, RATE_WINDOW),
+        })
+    }
+
+    /// Holds back requests to the endpoint for one rate window after OKX
+    /// reports a limit hit.
+    fn back_off(&self, limiter: &RateLimiter) -> FetchError {
+        limiter.pause(RATE_WINDOW);
+        FetchError::RateLimited {
+            until: chrono::Utc::now().timestamp_millis() + RATE_WINDOW.as_millis() as i64,
+        }
+    }
+}
+
+#[async_trait]
+impl MarketDataSource for Okx {
+    fn exchange(&self) -> Exchange {
+        Exchange::Okx
+    }
+
+    fn market(&self) -> Market {
+        Market::UsdM
+    }
+
+    async fn list_symbols(&self) -> Result<ExchangeInfo> {
+        self.limiter.acquire(1).await;
+        let body = self
+            .client
+            .get(format!("{}/api/v5/public/instruments", self.base_url))
+            .query(&[("instType", "SWAP")])
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        Ok(ExchangeInfo {
+            symbols: parse_instruments(&body)?,
+            rate_limits: Vec::new(),
+        })
+    }
+
+    async fn fetch_candles(&self, query: &CandleQuery<'_>) -> Result<Vec<Kline>, FetchError> {
+        let unsupported = || FetchError::InvalidRequest {
+            message: format!("OKX does not support the {} interval", query.interval),
+        };
+        let interval_ms = market_data::interval_millis(query.interval).ok_or_else(unsupported)?;
+        // `/market/candles` only reaches back 1440 candles, older pages come
+        // from `/market/history-candles`.
+        let (path, max_limit) = if query.end_time.is_some() {
+            ("history-candles", self.max_history_limit())
+        } else {
+            ("candles", self.max_limit())
+        };
+        let limiter = self.rate_limiter_for(query);
+        let mut params = vec![
+            ("instId", query.symbol.to_string()),
+            (
+                "bar",
+                okx_bar(query.interval).ok_or_else(unsupported)?.to_string(),
+            ),
+            ("limit", query.limit.min(max_limit).to_string()),
+        ];
+        // `before` returns candles strictly newer, `after` strictly older than the given time.
+        if let Some(start) = query.start_time {
+            params.push(("before", (start - 1).to_string()));
+        }
+        if let Some(end) = query.end_time {
+            params.push(("after", (end + 1).to_string()));
+        }
+
+        let response = self
+            .client
+            .get(format!("{}/api/v5/market/{}", self.base_url, path))
+            .query(&params)
+            .send()
+            .await?;
+        let status = response.status();
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(self.back_off(limiter));
+        }
+        if !status.is_success() {
+            return Err(FetchError::HttpStatus {
+                status: status.as_u16(),
+            });
+        }
+
+        let body = response.text().await?;
+        let envelope = serde_json::from_str::<Envelope<Value>>(&body)?;
+        if envelope.code == RATE_LIMITED_CODE {
+            return Err(self.back_off(limiter));
+        }
+        if envelope.code != "0" {
+            return Err(FetchError::Api {
+                code: envelope.code,
+                message: envelope.msg,
+            });
+        }
+
+        parse_klines(&body, interval_ms).map_err(|e| FetchError::Decode {
+            message: e.to_string(),
+        })
+    }
+
+    fn max_limit(&self) -> u32 {
+        MAX_LIMIT
+    }
+
+    fn max_history_limit(&self) -> u32 {
+        MAX_HISTORY_LIMIT
+    }
+
+    fn request_weight(&self, _limit: u32) -> u32 {
+        1
+    }
+
+    fn weight_limit(&self, _exchange_info: &ExchangeInfo) -> u32 {
+        REQUESTS_PER_WINDOW
+    }
+
+    fn rate_limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+
+    fn rate_limiter_for(&self, query: &CandleQuery<'_>) -> &RateLimiter {
+        match query.end_time {
+            Some(_) => &self.history_limiter,
+            None => &self.limiter,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::find_tickers::matches_filters;
+    use crate::storage_utils::{AppConfig, DEFAULT_CONFIG};
+
+    const INSTRUMENTS: &str = include_str!("../tests/fixtures/okx/instruments.json");
+    const CANDLES: &str = include_str!("../tests/fixtures/okx/candles.json");
+    const ERROR: &str = include_str!("../tests/fixtures/okx/error.json");
+
+    fn field<'a>(instrument: &'a Map<String, Value>, key: &str) -> &'a str {
+        instrument[key].as_str().unwrap()
+    }
+
+    #[test]
+    fn linear_instruments_are_normalized() {
+        let instruments = parse_instruments(INSTRUMENTS).unwrap();
+        let symbols: Vec<&str> = instruments.iter().map(|i| field(i, "symbol")).collect();
+        assert_eq!(symbols, ["BTC-USDT-SWAP", "LUNA-USDT-SWAP"]);
+
+        let btc = &instruments[0];
+        assert_eq!(field(btc, "status"), "TRADING");
+        assert_eq!(field(btc, "contractType"), "PERPETUAL");
+        assert_eq!(field(btc, "baseAsset"), "BTC");
+        assert_eq!(field(btc, "quoteAsset"), "USDT");
+        assert!(!btc.contains_key("underlyingType"));
+        assert_eq!(btc["underlyingSubType"], Value::Array(Vec::new()));
+        assert_eq!(field(&instruments[1], "status"), "SUSPEND");
+    }
+
+    #[test]
+    fn default_filters_keep_live_perpetuals() {
+        // Configs written before `exchange_filters` existed get the same defaults.
+        let legacy = r#"{"exchange": "okx", "klines": {"limit": 500, "interval": "1m"},
+            "filters": {"status": "TRADING", "underlyingType": "COIN"}}"#;
+        for config in [DEFAULT_CONFIG, legacy] {
+            let config: AppConfig = serde_json::from_str(config).unwrap();
+            let filters = config.filters_for(Exchange::Okx, Market::UsdM);
+            let kept: Vec<String> = parse_instruments(INSTRUMENTS)
+                .unwrap()
+                .into_iter()
+                .filter(|i| matches_filters(i, filters))
+                .map(|i| field(&i, "symbol").to_string())
+                .collect();
+            assert_eq!(kept, ["BTC-USDT-SWAP"]);
+        }
+    }
+
+    #[test]
+    fn candles_are_oldest_first_with_derived_close_time() {
+        let klines = parse_klines(CANDLES, 60_000).unwrap();
+
+        let open_times: Vec<i64> = klines.iter().map(|k| k.open_time).collect();
+        assert_eq!(open_times, [1700000000000, 1700000060000, 1700000120000]);
+        let first = &klines[0];
+        assert_eq!(first.close_time, 1700000059999);
+        assert_eq!(first.open, 36990.5);
+        assert_eq!(first.close, 37004.3);
+        // Volumes come from the base and quote currency columns, not contracts.
+        assert_eq!(first.volume, 28.1);
+        assert_eq!(first.quote_volume, 1039658.22);
+    }
+
+    #[test]
+    fn error_envelopes_are_reported() {
+        for error in [
+            parse_klines(ERROR, 60_000).unwrap_err(),
+            parse_instruments(ERROR).unwrap_err(),
+        ] {
+            assert_eq!(
+                error.to_string(),
+                "OKX error 51001: Instrument ID does not exist"
+            );
+        }
+    }
+
+    #[test]
+    fn bars_use_utc_variants_from_six_hours() {
+        assert_eq!(okx_bar("1h"), Some("1H"));
+        assert_eq!(okx_bar("6h"), Some("6Hutc"));
+        assert_eq!(okx_bar("1d"), Some("1Dutc"));
+        assert_eq!(okx_bar("7m"), None);
+    }
+
+    #[test]
+    fn history_pages_have_their_own_cap_and_limiter() {
+        let okx = Okx::new().unwrap();
+        let query = |end_time| CandleQuery {
+            symbol: "BTC-USDT-SWAP",
+            interval: "1h",
+            limit: 300,
+            start_time: None,
+            end_time,
+        };
+        assert_eq!(okx.max_limit(), 300);
+        assert_eq!(okx.max_history_limit(), 100);
+        assert!(std::ptr::eq(
+            okx.rate_limiter_for(&query(None)),
+            okx.rate_limiter()
+        ));
+        assert!(std::ptr::eq(
+            okx.rate_limiter_for(&query(Some(0))),
+            &okx.history_limiter
+        ));
+    }
+}
@@ -0,0 +1,215 @@
+//! Progress events sent by the analysis pipeline while a refresh runs.
+//!
+//! The pipeline only emits events; [`RefreshProgress`] folds them into the
+//! state the TUI renders.
+
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+// EVENTS
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    ExchangeInfo,
+    Klines,
+    Analysis,
+}
+
+impl Stage {
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::ExchangeInfo => "Fetching symbols",
+            Stage::Klines => "Downloading klines",
+            Stage::Analysis => "Analyzing",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    Stage(Stage),
+    /// `count` more symbols are queued for download.
+    SymbolsPlanned {
+        count: usize,
+    },
+    /// One symbol finished downloading, successfully or not.
+    SymbolFetched {
+        failed: bool,
+    },
+    /// Requests are held back by the rate limiter for `wait`.
+    RateLimited {
+        wait: Duration,
+    },
+}
+
+/// Sending side handed to the pipeline. Reporting is best effort: events are
+/// dropped when nobody listens.
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    tx: Option<mpsc::UnboundedSender<ProgressEvent>>,
+}
+
+impl ProgressReporter {
+    pub fn new(tx: mpsc::UnboundedSender<ProgressEvent>) -> Self {
+        Self { tx: Some(tx) }
+    }
+
+    pub fn send(&self, event: ProgressEvent) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(event);
+        }
+    }
+}
+
+// STATE
+
+/// Everything the progress popup shows about the running refresh.
+///
+/// Klines used to be downloaded in fixed batches with a pause between them.
+/// The rate limiter now paces requests continuously, so progress is tracked
+/// per symbol, and the only wait worth showing is a rate limit pause.
+pub struct RefreshProgress {
+    pub stage: Stage,
+    pub fetched: usize,
+    pub total: usize,
+    pub errors: usize,
+    klines_started: Option<Instant>,
+    resume_at: Option<Instant>,
+}
+
+impl Default for RefreshProgress {
+    fn default() -> Self {
+        Self {
+            stage: Stage::ExchangeInfo,
+            fetched: 0,
+            total: 0,
+            errors: 0,
+            klines_started: None,
+            resume_at: None,
+        }
+    }
+}
+
+impl RefreshProgress {
+    pub fn apply(&mut self, event: ProgressEvent) {
+        match event {
+            ProgressEvent::Stage(stage) => {
+                self.stage = stage;
+                if stage == Stage::Klines {
+                    self.klines_started = Some(Instant::now());
+                }
+            }
+            ProgressEvent::SymbolsPlanned { count } => self.total += count,
+            ProgressEvent::SymbolFetched { failed } => {
+                self.fetched += 1;
+                if failed {
+                    self.errors += 1;
+                }
+            }
+            ProgressEvent::RateLimited { wait } => {
+                let until = Instant::now() + wait;
+                if self.resume_at.is_none_or(|current| current < until) {
+                    self.resume_at = Some(until);
+                }
+            }
+        }
+    }
+
+    /// Share of the symbols downloaded so far, from 0 to 1.
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            (self.fetched as f64 / self.total as f64).min(1.0)
+        }
+    }
+
+    /// Time left for the downloads, extrapolated from the pace so far.
+    pub fn eta(&self) -> Option<Duration> {
+        let started = self.klines_started?;
+        if self.fetched == 0 || self.stage != Stage::Klines {
+            return None;
+        }
+        let remaining = self.total.saturating_sub(self.fetched) as f64;
+        let per_symbol = started.elapsed().as_secs_f64() / self.fetched as f64;
+        Some(Duration::from_secs_f64(per_symbol * remaining))
+    }
+
+    /// Time until rate limited requests go out again, if they are held back.
+    pub fn rate_limit_wait(&self) -> Option<Duration> {
+        self.resume_at
+            .and_then(|at| at.checked_duration_since(Instant::now()))
+            .filter(|wait| !wait.is_zero())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn downloading(total: usize) -> RefreshProgress {
+        let mut progress = RefreshProgress::default();
+        progress.apply(ProgressEvent::Stage(Stage::Klines));
+        progress.apply(ProgressEvent::SymbolsPlanned { count: total });
+        progress
+    }
+
+    #[test]
+    fn ratio_counts_fetched_symbols_and_is_capped() {
+        let mut progress = RefreshProgress::default();
+        assert_eq!(progress.ratio(), 0.0);
+
+        progress.apply(ProgressEvent::SymbolsPlanned { count: 3 });
+        progress.apply(ProgressEvent::SymbolsPlanned { count: 1 });
+        progress.apply(ProgressEvent::SymbolFetched { failed: false });
+        progress.apply(ProgressEvent::SymbolFetched { failed: true });
+        assert_eq!(progress.ratio(), 0.5);
+        assert_eq!(progress.errors, 1);
+
+        for _ in 0..3 {
+            progress.apply(ProgressEvent::SymbolFetched { failed: false });
+        }
+        assert_eq!(progress.ratio(), 1.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn eta_extrapolates_the_pace_so_far() {
+        let mut progress = downloading(10);
+        assert_eq!(progress.eta(), None);
+
+        tokio::time::advance(Duration::from_secs(4)).await;
+        progress.apply(ProgressEvent::SymbolFetched { failed: false });
+        progress.apply(ProgressEvent::SymbolFetched { failed: false });
+        assert_eq!(progress.eta(), Some(Duration::from_secs(16)));
+
+        progress.apply(ProgressEvent::Stage(Stage::Analysis));
+        assert_eq!(progress.eta(), None);
+    }
+
+    #[test]
+    fn eta_needs_the_download_to_have_started() {
+        let mut progress = RefreshProgress::default();
+        progress.apply(ProgressEvent::SymbolsPlanned { count: 2 });
+        progress.apply(ProgressEvent::SymbolFetched { failed: false });
+        assert_eq!(progress.eta(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limit_wait_keeps_the_latest_resume_time() {
+        let mut progress = downloading(1);
+        assert_eq!(progress.rate_limit_wait(), None);
+
+        progress.apply(ProgressEvent::RateLimited {
+            wait: Duration::from_secs(5),
+        });
+        progress.apply(ProgressEvent::RateLimited {
+            wait: Duration::from_secs(2),
+        });
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(progress.rate_limit_wait(), Some(Duration::from_secs(4)));
+
+        tokio::time::advance(Duration::from_secs(4)).await;
+        assert_eq!(progress.rate_limit_wait(), None);
+    }
+}
@@ -0,0 +1,227 @@
+//! Token bucket shared by every request a market data source makes.
+//!
+//! Weight trickles back into the bucket at `capacity / window`, so requests are
+//! paced evenly instead of being fired in per-window bursts. Sources feed the
+//! limiter what the venue reports (used weight, `Retry-After`, bans) so the
+//! local estimate never drifts far from the server's view.
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+
+/// The bucket holds at most this fraction of a window's capacity, which bounds
+/// how many requests can go out back to back.
+const BURST_DIVISOR: f64 = 20.0;
+
+struct Bucket {
+    capacity: f64,
+    window: Duration,
+    tokens: f64,
+    last_refill: Instant,
+    paused_until: Option<Instant>,
+}
+
+impl Bucket {
+    fn rate_per_sec(&self) -> f64 {
+        self.capacity / self.window.as_secs_f64()
+    }
+
+    fn burst(&self) -> f64 {
+        (self.capacity / BURST_DIVISOR).max(1.0)
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate_per_sec()).min(self.burst());
+        self.last_refill = now;
+    }
+}
+
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    /// A limiter allowing `capacity` weight per `window`.
+    pub fn new(capacity: u32, window: Duration) -> Self {
+        let mut bucket = Bucket {
+            capacity: capacity.max(1) as f64,
+            window,
+            tokens: 0.0,
+            last_refill: Instant::now(),
+            paused_until: None,
+        };
+        bucket.tokens = bucket.burst();
+        Self {
+            bucket: Mutex::new(bucket),
+        }
+    }
+
+    /// Replaces the weight allowed per window, e.g. once the venue's published
+    /// limits are known.
+    pub fn set_capacity(&self, capacity: u32) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.refill(Instant::now());
+        bucket.capacity = capacity.max(1) as f64;
+        bucket.tokens = bucket.tokens.min(bucket.burst());
+    }
+
+    /// Waits until `weight` can be spent without exceeding the budget, then spends it.
+    pub async fn acquire(&self, weight: u32) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                bucket.refill(now);
+
+                match bucket.paused_until {
+                    Some(until) if until > now => until - now,
+                    _ => {
+                        // Requests heavier than the burst size go out once the
+                        // bucket is full, leaving it in debt.
+                        let needed = (weight as f64).min(bucket.burst());
+                        if bucket.tokens >= needed {
+                            bucket.tokens -= weight as f64;
+                            return;
+                        }
+                        Duration::from_secs_f64((needed - bucket.tokens) / bucket.rate_per_sec())
+                    }
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Aligns the bucket with the weight the venue reports as used in its
+    /// current window. Once the budget is spent, requests pause until the
+    /// window rolls over.
+    pub fn observe_used_weight(&self, used: u32) {
+        let mut bucket = self.bucket.lock().unwrap();
+        let remaining = bucket.capacity - used as f64;
+        bucket.tokens = bucket.tokens.min(remaining);
+
+        if remaining <= 0.0 {
+            let now_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis();
+            let until_rollover = until_next_window(bucket.window, now_ms);
+            drop(bucket);
+            self.pause(until_rollover);
+        }
+    }
+
+    /// Time left on the current pause, if requests are held back.
+    pub fn paused_for(&self) -> Option<Duration> {
+        let bucket = self.bucket.lock().unwrap();
+        bucket
+            .paused_until
+            .and_then(|until| until.checked_duration_since(Instant::now()))
+            .filter(|wait| !wait.is_zero())
+    }
+
+    /// Holds back every request for `duration`, e.g. after a `Retry-After`.
+    pub fn pause(&self, duration: Duration) {
+        let mut bucket = self.bucket.lock().unwrap();
+        let until = Instant::now() + duration;
+        if bucket.paused_until.is_none_or(|current| current < until) {
+            bucket.paused_until = Some(until);
+        }
+    }
+}
+
+/// Time left from `now_ms` until the next window boundary. Venues count
+/// weight in windows aligned to the wall clock, e.g. whole minutes.
+fn until_next_window(window: Duration, now_ms: u128) -> Duration {
+    let window_ms = window.as_millis().max(1);
+    Duration::from_millis((window_ms - now_ms % window_ms) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 20 weight per second with a burst of 60.
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(1200, Duration::from_secs(60))
+    }
+
+    async fn time(acquire: impl Future<Output = ()>) -> Duration {
+        let start = Instant::now();
+        acquire.await;
+        start.elapsed()
+    }
+
+    /// Sleeps are rounded up to the timer's millisecond resolution.
+    fn assert_waited(waited: Duration, expected: Duration) {
+        assert!(
+            waited >= expected && waited <= expected + Duration::from_millis(1),
+            "waited {:?}, expected {:?}",
+            waited,
+            expected
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn requests_are_paced_once_the_burst_is_spent() {
+        let limiter = limiter();
+        for _ in 0..60 {
+            assert_eq!(time(limiter.acquire(1)).await, Duration::ZERO);
+        }
+        assert_waited(time(limiter.acquire(1)).await, Duration::from_millis(50));
+        assert_waited(time(limiter.acquire(10)).await, Duration::from_millis(500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn heavy_requests_wait_for_a_full_bucket_and_leave_it_in_debt() {
+        let limiter = limiter();
+        limiter.acquire(30).await;
+        assert_waited(
+            time(limiter.acquire(100)).await,
+            Duration::from_millis(1500),
+        );
+        // 40 weight of debt plus the next request's own weight.
+        assert_waited(time(limiter.acquire(1)).await, Duration::from_millis(2050));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reported_weight_caps_the_bucket() {
+        let limiter = limiter();
+        limiter.observe_used_weight(1195);
+        assert_eq!(limiter.paused_for(), None);
+        assert_eq!(time(limiter.acquire(5)).await, Duration::ZERO);
+        assert_waited(time(limiter.acquire(1)).await, Duration::from_millis(50));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_spent_budget_pauses_until_the_window_rolls_over() {
+        let limiter = limiter();
+        limiter.observe_used_weight(1200);
+        let paused = limiter.paused_for().unwrap();
+        assert!(paused <= Duration::from_secs(60));
+        assert_waited(time(limiter.acquire(1)).await, paused);
+        assert_eq!(limiter.paused_for(), None);
+    }
+
+    #[test]
+    fn windows_roll_over_on_wall_clock_boundaries() {
+        let minute = Duration::from_secs(60);
+        assert_eq!(until_next_window(minute, 125_000), Duration::from_secs(55));
+        assert_eq!(until_next_window(minute, 120_000), minute);
+        assert_eq!(until_next_window(minute, 179_999), Duration::from_millis(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pauses_hold_requests_back_and_only_extend() {
+        let limiter = limiter();
+        limiter.pause(Duration::from_secs(2));
+        limiter.pause(Duration::from_secs(1));
+        assert_eq!(limiter.paused_for(), Some(Duration::from_secs(2)));
+        assert_waited(time(limiter.acquire(1)).await, Duration::from_secs(2));
+        assert_eq!(limiter.paused_for(), None);
+
+        limiter.pause(Duration::from_secs(1));
+        limiter.pause(Duration::from_secs(3));
+        assert_waited(time(limiter.acquire(1)).await, Duration::from_secs(3));
+    }
+}
@@ -0,0 +1,200 @@
+//! Retrying failed requests with exponential backoff and jitter.
+//!
+//! Every attempt goes back through the caller's closure, so attempts that
+//! acquire from a [`RateLimiter`](crate::rate_limit::RateLimiter) spend weight
+//! like any other request, and wait out pauses set by earlier failures.
+
+use crate::market_data::FetchError;
+use crate::storage_utils::RetryConfig;
+use std::time::Duration;
+
+/// Whether a failed candle request is worth another attempt.
+pub fn is_retryable(error: &FetchError, config: &RetryConfig) -> bool {
+    match error {
+        FetchError::Network { .. } => true,
+        FetchError::HttpStatus { status } => config.retry_statuses.contains(status),
+        FetchError::RateLimited { .. } => config.retry_statuses.contains(&429),
+        FetchError::Banned { .. } => config.retry_statuses.contains(&418),
+        FetchError::Api { .. }
+        | FetchError::Decode { .. }
+        | FetchError::EmptyResponse
+        | FetchError::InvalidRequest { .. } => false,
+    }
+}
+
+/// [`is_retryable`] for requests that report `anyhow` errors, such as
+/// listing symbols.
+pub fn is_retryable_anyhow(error: &anyhow::Error, config: &RetryConfig) -> bool {
+    if let Some(fetch_error) = error.downcast_ref::<FetchError>() {
+        return is_retryable(fetch_error, config);
+    }
+    match error.downcast_ref::<reqwest::Error>() {
+        Some(e) => match e.status() {
+            Some(status) => config.retry_statuses.contains(&status.as_u16()),
+            None => !e.is_decode(),
+        },
+        None => false,
+    }
+}
+
+/// Delay before the `retry`-th retry (starting at 1): the base delay doubled
+/// per retry, capped, with the configured fraction of it randomized.
+pub fn backoff_delay(config: &RetryConfig, retry: u32) -> Duration {
+    let exponential = config
+        .base_delay_ms
+        .saturating_mul(1u64 << retry.saturating_sub(1).min(20))
+        .min(config.max_delay_ms);
+    let jitter = config.jitter.clamp(0.0, 1.0);
+    let delay = exponential as f64 * (1.0 - jitter * rand::random::<f64>());
+    Duration::from_millis(delay as u64)
+}
+
+/// Runs `attempt` until it succeeds, fails with an error `retryable` rejects,
+/// or `max_attempts` is used up. The last error is returned.
+pub async fn with_retry<T, E, F, Fut>(
+    config: &RetryConfig,
+    retryable: impl Fn(&E) -> bool,
+    mut attempt: F,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut retries = 0;
+    loop {
+        match attempt().await {
+            Err(e) if retries + 1 < config.max_attempts && retryable(&e) => {
+                retries += 1;
+                tokio::time::sleep(backoff_delay(config, retries)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use tokio::time::Instant;
+
+    fn config(jitter: f64) -> RetryConfig {
+        RetryConfig {
+            max_attempts: 4,
+            base_delay_ms: 100,
+            max_delay_ms: 300,
+            jitter,
+            retry_statuses: vec![429, 503],
+        }
+    }
+
+    #[test]
+    fn delays_double_up_to_the_cap() {
+        let config = config(0.0);
+        let delays: Vec<u64> = (1..=5)
+            .map(|retry| backoff_delay(&config, retry).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [100, 200, 300, 300, 300]);
+        assert_eq!(backoff_delay(&config, u32::MAX).as_millis(), 300);
+    }
+
+    #[test]
+    fn jitter_only_shortens_delays_by_its_fraction() {
+        for retry in 1..=4 {
+            let capped = backoff_delay(&config(0.0), retry);
+            for _ in 0..200 {
+                let delay = backoff_delay(&config(0.25), retry);
+                assert!(delay <= capped);
+                assert!(delay >= capped.mul_f64(0.75) - Duration::from_millis(1));
+            }
+        }
+        // Out of range fractions are clamped rather than lengthening delays.
+        assert_eq!(backoff_delay(&config(-1.0), 1), Duration::from_millis(100));
+        assert!(backoff_delay(&config(3.0), 1) <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn statuses_are_classified_by_the_config() {
+        let config = config(0.0);
+        let retryable = |e: FetchError| is_retryable(&e, &config);
+        assert!(retryable(FetchError::Network {
+            message: "reset".to_string()
+        }));
+        assert!(retryable(FetchError::HttpStatus { status: 503 }));
+        assert!(!retryable(FetchError::HttpStatus { status: 500 }));
+        assert!(retryable(FetchError::RateLimited { until: 0 }));
+        assert!(!retryable(FetchError::Banned { until: 0 }));
+        assert!(!retryable(FetchError::Api {
+            code: "-1121".to_string(),
+            message: "Invalid symbol.".to_string()
+        }));
+        assert!(!retryable(FetchError::Decode {
+            message: "eof".to_string()
+        }));
+        assert!(!retryable(FetchError::EmptyResponse));
+        assert!(!retryable(FetchError::InvalidRequest {
+            message: "interval".to_string()
+        }));
+
+        let wrapped = anyhow::Error::new(FetchError::HttpStatus { status: 429 });
+        assert!(is_retryable_anyhow(&wrapped, &config));
+        assert!(!is_retryable_anyhow(
+            &anyhow::anyhow!("bad config"),
+            &config
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_until_attempts_run_out() {
+        let config = config(0.0);
+        let attempts = Cell::new(0);
+        let start = Instant::now();
+        let result: Result<(), u32> = with_retry(
+            &config,
+            |_| true,
+            || async {
+                attempts.set(attempts.get() + 1);
+                Err(attempts.get())
+            },
+        )
+        .await;
+        assert_eq!(result, Err(4));
+        assert_eq!(attempts.get(), 4);
+        assert_eq!(start.elapsed(), Duration::from_millis(100 + 200 + 300));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stops_at_success_or_a_final_error() {
+        let config = config(0.0);
+        let attempts = Cell::new(0);
+        let result: Result<u32, u32> = with_retry(
+            &config,
+            |_| true,
+            || async {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() < 2 {
+                    Err(0)
+                } else {
+                    Ok(attempts.get())
+                }
+            },
+        )
+        .await;
+        assert_eq!(result, Ok(2));
+
+        attempts.set(0);
+        let start = Instant::now();
+        let result: Result<(), u32> = with_retry(
+            &config,
+            |e| *e != 7,
+            || async {
+                attempts.set(attempts.get() + 1);
+                Err(7)
+            },
+        )
+        .await;
+        assert_eq!(result, Err(7));
+        assert_eq!(attempts.get(), 1);
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+}
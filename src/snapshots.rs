@@ -0,0 +1,148 @@
+//! Timestamped copies of the results of every pipeline run, kept by the
+//! storage backend and pruned by the configured retention policy.
+
+use crate::storage::Storage;
+use crate::storage_utils::SnapshotConfig;
+use crate::tui::OutputData;
+use anyhow::Result;
+use serde::Serialize;
+
+/// A stored snapshot, identified by the time its run finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    pub taken_at: i64,
+}
+
+/// Every stored snapshot, newest first.
+pub async fn list(storage: &dyn Storage) -> Result<Vec<SnapshotInfo>> {
+    let mut snapshots: Vec<SnapshotInfo> = storage
+        .list_snapshots()
+        .await?
+        .into_iter()
+        .map(|taken_at| SnapshotInfo { taken_at })
+        .collect();
+    snapshots.sort_unstable_by_key(|s| std::cmp::Reverse(s.taken_at));
+    Ok(snapshots)
+}
+
+pub async fn load(storage: &dyn Storage, info: SnapshotInfo) -> Result<OutputData> {
+    storage.load_snapshot(info.taken_at).await
+}
+
+/// Stores `results` as the snapshot of a run finished at `taken_at`, then
+/// prunes snapshots the retention policy no longer keeps.
+pub async fn save<T: Serialize>(
+    storage: &dyn Storage,
+    results: &T,
+    taken_at: i64,
+    config: &SnapshotConfig,
+) -> Result<()> {
+    storage.save_snapshot(taken_at, results).await?;
+    prune(storage, config, taken_at).await
+}
+
+/// Removes snapshots beyond the newest `keep_count`, and those older than
+/// `max_age_hours` before `now`.
+async fn prune(storage: &dyn Storage, config: &SnapshotConfig, now: i64) -> Result<()> {
+    let oldest_kept = config
+        .max_age_hours
+        .map(|hours| now - hours as i64 * 3_600_000);
+
+    for (i, info) in list(storage).await?.into_iter().enumerate() {
+        let too_many = config.keep_count.is_some_and(|keep| i >= keep);
+        let too_old = oldest_kept.is_some_and(|oldest| info.taken_at < oldest);
+        if too_many || too_old {
+            storage.delete_snapshot(info.taken_at).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sqlite_storage::SqliteStorage;
+    use crate::storage::JsonStorage;
+    use crate::storage_utils::AsyncStorageManager;
+    use std::path::PathBuf;
+
+    const HOUR: i64 = 3_600_000;
+
+    /// Both backends, each in a fresh directory under `name`.
+    async fn backends(name: &str) -> (PathBuf, Vec<Box<dyn Storage>>) {
+        let dir = std::env::temp_dir().join(format!(
+            "screener-snapshots-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let json = AsyncStorageManager::new_in(dir.join("json")).await.unwrap();
+        let sqlite_dir = dir.join("sqlite");
+        std::fs::create_dir_all(&sqlite_dir).unwrap();
+        let storages: Vec<Box<dyn Storage>> = vec![
+            Box::new(JsonStorage::new(json)),
+            Box::new(SqliteStorage::open(&sqlite_dir).await.unwrap()),
+        ];
+        (dir, storages)
+    }
+
+    /// Saves one snapshot an hour, from hour 0 to `hours - 1`.
+    async fn save_hourly(storage: &dyn Storage, hours: i64, config: &SnapshotConfig) {
+        for hour in 0..hours {
+            save(storage, &hour, hour * HOUR, config).await.unwrap();
+        }
+    }
+
+    async fn kept_hours(storage: &dyn Storage) -> Vec<i64> {
+        list(storage)
+            .await
+            .unwrap()
+            .iter()
+            .map(|s| s.taken_at / HOUR)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn only_the_newest_keep_count_snapshots_are_kept() {
+        let (dir, storages) = backends("count").await;
+        let config = SnapshotConfig {
+            keep_count: Some(3),
+            max_age_hours: None,
+        };
+        for storage in &storages {
+            save_hourly(storage.as_ref(), 5, &config).await;
+            assert_eq!(kept_hours(storage.as_ref()).await, [4, 3, 2]);
+            let newest: i64 = storage.load_snapshot(4 * HOUR).await.unwrap();
+            assert_eq!(newest, 4);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn snapshots_older_than_max_age_are_dropped() {
+        let (dir, storages) = backends("age").await;
+        let config = SnapshotConfig {
+            keep_count: None,
+            max_age_hours: Some(2),
+        };
+        for storage in &storages {
+            save_hourly(storage.as_ref(), 5, &config).await;
+            assert_eq!(kept_hours(storage.as_ref()).await, [4, 3, 2]);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn without_limits_every_snapshot_is_kept() {
+        let (dir, storages) = backends("unlimited").await;
+        let config = SnapshotConfig {
+            keep_count: None,
+            max_age_hours: None,
+        };
+        for storage in &storages {
+            save_hourly(storage.as_ref(), 4, &config).await;
+            assert_eq!(kept_hours(storage.as_ref()).await, [3, 2, 1, 0]);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,655 @@
+//! SQLite implementation of [`Storage`], kept in `screener.db` in storage.
+//!
+//! Candles are keyed by exchange, market, symbol, interval and open time, so
+//! every refresh only upserts what it downloaded and candles of different
+//! intervals can be told apart. The `series` table marks which range of
+//! candles makes up each symbol's current series; candles outside every
+//! series, such as those of delisted symbols or another interval, are dropped
+//! on save so the database does not grow with every refresh.
+
+use crate::find_tickers::{ExchangeInfo, RateLimit};
+use crate::klines::{FetchFailure, KLINE_KEYS, Kline, KlineResult};
+use crate::storage::{self, RunMetadata, SeriesVisitor, Storage};
+use crate::storage_utils::{Exchange, Market};
+use anyhow::Result;
+use async_trait::async_trait;
+use rusqlite::types::{Type, Value as SqlValue, ValueRef};
+use rusqlite::{Connection, OptionalExtension, params, params_from_iter};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const DB_FILE: &str = "screener.db";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS exchange_info (
+        source TEXT PRIMARY KEY,
+        rate_limits TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS symbols (
+        source TEXT NOT NULL,
+        symbol TEXT NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (source, symbol)
+    );
+    CREATE TABLE IF NOT EXISTS series (
+        exchange TEXT NOT NULL,
+        market TEXT NOT NULL,
+        symbol TEXT NOT NULL,
+        interval TEXT NOT NULL,
+        underlying_sub_type TEXT NOT NULL,
+        first_open_time INTEGER NOT NULL,
+        last_open_time INTEGER NOT NULL,
+        PRIMARY KEY (exchange, market, symbol)
+    );
+    CREATE TABLE IF NOT EXISTS fetch_errors (
+        symbol TEXT NOT NULL,
+        exchange TEXT NOT NULL,
+        market TEXT NOT NULL,
+        error TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS results (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        body TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS snapshots (
+        taken_at INTEGER PRIMARY KEY,
+        body TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        started_at INTEGER NOT NULL,
+        finished_at INTEGER NOT NULL,
+        outcome TEXT NOT NULL,
+        symbols INTEGER NOT NULL,
+        failures INTEGER NOT NULL
+    );
+";
+
+/// The candles table has one column per kline field, named like the field.
+fn candles_schema() -> String {
+    let columns: Vec<String> = KLINE_KEYS.iter().map(|k| format!("\"{}\"", k)).collect();
+    format!(
+        "CREATE TABLE IF NOT EXISTS candles (
+            exchange TEXT NOT NULL,
+            market TEXT NOT NULL,
+            symbol TEXT NOT NULL,
+            interval TEXT NOT NULL,
+            {},
+            PRIMARY KEY (exchange, market, symbol, interval, \"openTime\")
+        ) WITHOUT ROWID;",
+        columns.join(", ")
+    )
+}
+
+fn candle_columns() -> String {
+    KLINE_KEYS
+        .iter()
+        .map(|k| format!("\"{}\"", k))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// CONVERSIONS
+
+/// The kline columns of a candle, in `KLINE_KEYS` order.
+fn candle_values(k: &Kline) -> [SqlValue; 11] {
+    let real = |v: Option<f64>| v.map_or(SqlValue::Null, SqlValue::Real);
+    [
+        SqlValue::Integer(k.open_time),
+        SqlValue::Real(k.open),
+        SqlValue::Real(k.high),
+        SqlValue::Real(k.low),
+        SqlValue::Real(k.close),
+        SqlValue::Real(k.volume),
+        SqlValue::Integer(k.close_time),
+        SqlValue::Real(k.quote_volume),
+        k.trades
+            .map_or(SqlValue::Null, |n| SqlValue::Integer(n as i64)),
+        real(k.taker_buy_base_volume),
+        real(k.taker_buy_quote_volume),
+    ]
+}
+
+/// Reads a numeric column, including decimals stored as text.
+fn number(value: ValueRef<'_>) -> Option<f64> {
+    match value {
+        ValueRef::Integer(i) => Some(i as f64),
+        ValueRef::Real(f) => Some(f),
+        ValueRef::Text(t) => std::str::from_utf8(t).ok()?.parse().ok(),
+        ValueRef::Null | ValueRef::Blob(_) => None,
+    }
+}
+
+/// Reads a stored identifier such as `usdt_m` back into its enum.
+fn parse_id<T: DeserializeOwned>(id: String) -> Result<T> {
+    Ok(serde_json::from_value(Value::String(id))?)
+}
+
+/// Reads the kline columns of a row, starting at column `offset`.
+fn candle_from_row(row: &rusqlite::Row<'_>, offset: usize) -> rusqlite::Result<Kline> {
+    let mut fields = [None; 11];
+    for (i, field) in fields.iter_mut().enumerate() {
+        *field = number(row.get_ref(offset + i)?);
+    }
+    Kline::from_fields::<serde_json::Error>(fields)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(offset, Type::Null, Box::new(e)))
+}
+
+// BACKEND
+
+pub struct SqliteStorage {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    pub async fn open(dir: &Path) -> Result<Self> {
+        let path = dir.join(DB_FILE);
+        let conn = tokio::task::spawn_blocking(move || -> Result<Connection> {
+            let conn = Connection::open(path)?;
+            conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")?;
+            conn.execute_batch(SCHEMA)?;
+            conn.execute_batch(&candles_schema())?;
+            Ok(conn)
+        })
+        .await??;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs `f` on the connection on the blocking pool, so no runtime thread
+    /// waits for the database.
+    async fn with_conn<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let conn = Arc::clone(&self.conn);
+        tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap())).await?
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn save_exchange_info(&self, key: &str, info: &ExchangeInfo) -> Result<()> {
+        let key = key.to_string();
+        let rate_limits = serde_json::to_string(&info.rate_limits)?;
+        let symbols = info
+            .symbols
+            .iter()
+            .map(|symbol| {
+                let name = symbol
+                    .get("symbol")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                Ok((name.to_string(), serde_json::to_string(symbol)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT OR REPLACE INTO exchange_info (source, rate_limits) VALUES (?1, ?2)",
+                params![key, rate_limits],
+            )?;
+            tx.execute("DELETE FROM symbols WHERE source = ?1", params![key])?;
+            {
+                let mut insert = tx.prepare(
+                    "INSERT OR REPLACE INTO symbols (source, symbol, data) VALUES (?1, ?2, ?3)",
+                )?;
+                for (name, data) in &symbols {
+                    insert.execute(params![key, name, data])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_exchange_info(&self, key: &str) -> Result<ExchangeInfo> {
+        let key = key.to_string();
+        self.with_conn(move |conn| {
+            let rate_limits: String = conn
+                .query_row(
+                    "SELECT rate_limits FROM exchange_info WHERE source = ?1",
+                    params![key],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or_else(|| anyhow::anyhow!("No symbols stored for {}", key))?;
+            let rate_limits: Vec<RateLimit> = serde_json::from_str(&rate_limits)?;
+
+            let mut select =
+                conn.prepare("SELECT data FROM symbols WHERE source = ?1 ORDER BY rowid")?;
+            let symbols = select
+                .query_map(params![key], |row| row.get::<_, String>(0))?
+                .map(|data| Ok(serde_json::from_str(&data?)?))
+                .collect::<Result<_>>()?;
+            Ok(ExchangeInfo {
+                symbols,
+                rate_limits,
+            })
+        })
+        .await
+    }
+
+    async fn save_klines(&self, series: &[KlineResult]) -> Result<()> {
+        let series = series.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM series", [])?;
+            {
+                let placeholders = vec!["?"; 4 + KLINE_KEYS.len()].join(", ");
+                let mut insert_candle = tx.prepare(&format!(
+                    "INSERT OR REPLACE INTO candles (exchange, market, symbol, interval, {}) VALUES ({})",
+                    candle_columns(),
+                    placeholders
+                ))?;
+                let mut insert_series = tx.prepare(
+                    "INSERT OR REPLACE INTO series (exchange, market, symbol, interval,
+                        underlying_sub_type, first_open_time, last_open_time)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                )?;
+
+                for s in &series {
+                    let key = [
+                        SqlValue::Text(s.exchange.id().to_string()),
+                        SqlValue::Text(s.market.id().to_string()),
+                        SqlValue::Text(s.symbol.clone()),
+                        SqlValue::Text(s.interval.clone()),
+                    ];
+                    for candle in &s.klines {
+                        let values = candle_values(candle);
+                        insert_candle.execute(params_from_iter(key.iter().chain(&values)))?;
+                    }
+
+                    let open_times = s.klines.iter().map(|k| k.open_time);
+                    let (Some(first), Some(last)) = (open_times.clone().min(), open_times.max())
+                    else {
+                        continue;
+                    };
+                    insert_series.execute(params![
+                        s.exchange.id(),
+                        s.market.id(),
+                        s.symbol,
+                        s.interval,
+                        serde_json::to_string(&s.underlying_sub_type)?,
+                        first,
+                        last
+                    ])?;
+                }
+            }
+            tx.execute(
+                "DELETE FROM candles WHERE NOT EXISTS (
+                    SELECT 1 FROM series
+                    WHERE series.exchange = candles.exchange
+                      AND series.market = candles.market
+                      AND series.symbol = candles.symbol
+                      AND series.interval = candles.interval
+                      AND candles.\"openTime\" BETWEEN series.first_open_time
+                                                   AND series.last_open_time
+                )",
+                [],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_klines(&self) -> Result<Vec<KlineResult>> {
+        let mut series = Vec::new();
+        self.for_each_series(&mut |s| {
+            series.push(s);
+            Ok(())
+        })
+        .await?;
+        Ok(series)
+    }
+
+    async fn for_each_series(&self, visit: &mut SeriesVisitor<'_>) -> Result<()> {
+        let conn = Arc::clone(&self.conn);
+        let read = move |send: &mut dyn FnMut(KlineResult) -> Result<()>| {
+            let conn = conn.lock().unwrap();
+            let mut select_series = conn.prepare(
+                "SELECT exchange, market, symbol, interval, underlying_sub_type,
+                        first_open_time, last_open_time
+                 FROM series ORDER BY rowid",
+            )?;
+            let mut select_candles = conn.prepare(&format!(
+                "SELECT {} FROM candles
+                 WHERE exchange = ?1 AND market = ?2 AND symbol = ?3 AND interval = ?4
+                   AND \"openTime\" BETWEEN ?5 AND ?6
+                 ORDER BY \"openTime\"",
+                candle_columns()
+            ))?;
+
+            let rows = select_series
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, String>(4)?,
+                        row.get::<_, i64>(5)?,
+                        row.get::<_, i64>(6)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            for (exchange, market, symbol, interval, sub_types, first, last) in rows {
+                let klines = select_candles
+                    .query_map(
+                        params![exchange, market, symbol, interval, first, last],
+                        |row| candle_from_row(row, 0),
+                    )?
+                    .collect::<rusqlite::Result<_>>()?;
+                send(KlineResult {
+                    symbol,
+                    exchange: parse_id(exchange)?,
+                    market: parse_id(market)?,
+                    interval,
+                    underlying_sub_type: serde_json::from_str(&sub_types)?,
+                    klines,
+                })?;
+            }
+            Ok(())
+        };
+        storage::visit_blocking(read, visit).await
+    }
+
+    async fn load_candles(&self, interval: &str, since: i64) -> Result<Vec<KlineResult>> {
+        let interval = interval.to_string();
+        self.with_conn(move |conn| {
+            let mut select = conn.prepare(&format!(
+                "SELECT exchange, market, symbol, {} FROM candles
+                 WHERE interval = ?1 AND \"openTime\" >= ?2
+                 ORDER BY exchange, market, symbol, \"openTime\"",
+                candle_columns()
+            ))?;
+            let rows = select.query_map(params![interval, since], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    candle_from_row(row, 3)?,
+                ))
+            })?;
+
+            // Rows come grouped by symbol, so a new series starts whenever the key changes.
+            let mut series: Vec<KlineResult> = Vec::new();
+            for row in rows {
+                let (exchange, market, symbol, candle) = row?;
+                let exchange: Exchange = parse_id(exchange)?;
+                let market: Market = parse_id(market)?;
+                match series.last_mut() {
+                    Some(last)
+                        if last.exchange == exchange
+                            && last.market == market
+                            && last.symbol == symbol =>
+                    {
+                        last.klines.push(candle)
+                    }
+                    _ => series.push(KlineResult {
+                        symbol,
+                        exchange,
+                        market,
+                        interval: interval.clone(),
+                        underlying_sub_type: Vec::new(),
+                        klines: vec![candle],
+                    }),
+                }
+            }
+            Ok(series)
+        })
+        .await
+    }
+
+    async fn save_fetch_errors(&self, failures: &[FetchFailure]) -> Result<()> {
+        let failures = failures.to_vec();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM fetch_errors", [])?;
+            {
+                let mut insert = tx.prepare(
+                    "INSERT INTO fetch_errors (symbol, exchange, market, error) VALUES (?1, ?2, ?3, ?4)",
+                )?;
+                for failure in &failures {
+                    insert.execute(params![
+                        failure.symbol,
+                        failure.exchange.id(),
+                        failure.market.id(),
+                        serde_json::to_string(&failure.error)?
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_fetch_errors(&self) -> Result<Vec<FetchFailure>> {
+        self.with_conn(|conn| {
+            let mut select = conn.prepare(
+                "SELECT symbol, exchange, market, error FROM fetch_errors ORDER BY rowid",
+            )?;
+            let rows = select.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?;
+            rows.map(|row| {
+                let (symbol, exchange, market, error) = row?;
+                Ok(FetchFailure {
+                    symbol,
+                    exchange: parse_id(exchange)?,
+                    market: parse_id(market)?,
+                    error: serde_json::from_str(&error)?,
+                })
+            })
+            .collect()
+        })
+        .await
+    }
+
+    async fn save_results_value(&self, results: Value) -> Result<()> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO results (id, body) VALUES (1, ?1)",
+                params![results.to_string()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_results_value(&self) -> Result<Value> {
+        self.with_conn(|conn| {
+            let body: String = conn
+                .query_row("SELECT body FROM results WHERE id = 1", [], |row| {
+                    row.get(0)
+                })
+                .optional()?
+                .ok_or_else(|| anyhow::anyhow!("No results stored yet"))?;
+            Ok(serde_json::from_str(&body)?)
+        })
+        .await
+    }
+
+    async fn record_run(&self, run: &RunMetadata) -> Result<()> {
+        let run = run.clone();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO runs (started_at, finished_at, outcome, symbols, failures)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    run.started_at,
+                    run.finished_at,
+                    serde_json::to_value(run.outcome)?.as_str(),
+                    run.symbols as i64,
+                    run.failures as i64
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn save_snapshot_value(&self, taken_at: i64, results: Value) -> Result<()> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO snapshots (taken_at, body) VALUES (?1, ?2)",
+                params![taken_at, results.to_string()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn load_snapshot_value(&self, taken_at: i64) -> Result<Value> {
+        self.with_conn(move |conn| {
+            let body: String = conn
+                .query_row(
+                    "SELECT body FROM snapshots WHERE taken_at = ?1",
+                    params![taken_at],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or_else(|| anyhow::anyhow!("No snapshot taken at {}", taken_at))?;
+            Ok(serde_json::from_str(&body)?)
+        })
+        .await
+    }
+
+    async fn list_snapshots(&self) -> Result<Vec<i64>> {
+        self.with_conn(|conn| {
+            let mut select = conn.prepare("SELECT taken_at FROM snapshots")?;
+            let taken_at = select
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(taken_at)
+        })
+        .await
+    }
+
+    async fn delete_snapshot(&self, taken_at: i64) -> Result<()> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "DELETE FROM snapshots WHERE taken_at = ?1",
+                params![taken_at],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: i64 = 60_000;
+
+    fn series(symbol: &str, interval: &str, open_times: std::ops::Range<i64>) -> KlineResult {
+        KlineResult {
+            symbol: symbol.to_string(),
+            exchange: Exchange::Binance,
+            market: Market::UsdM,
+            interval: interval.to_string(),
+            underlying_sub_type: Vec::new(),
+            klines: open_times
+                .map(|i| Kline::flat(i * MINUTE, 1.0, MINUTE))
+                .collect(),
+        }
+    }
+
+    /// A fresh database in its own directory, removed again on drop.
+    struct TempDb {
+        dir: std::path::PathBuf,
+        storage: SqliteStorage,
+    }
+
+    impl TempDb {
+        async fn open(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "screener-sqlite-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            let storage = SqliteStorage::open(&dir).await.unwrap();
+            Self { dir, storage }
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn open_times(series: &KlineResult) -> Vec<i64> {
+        series.klines.iter().map(|k| k.open_time / MINUTE).collect()
+    }
+
+    #[tokio::test]
+    async fn candles_outside_every_series_are_dropped() {
+        let db = TempDb::open("retention").await;
+        let storage = &db.storage;
+
+        storage
+            .save_klines(&[series("BTCUSDT", "1m", 0..5), series("ETHUSDT", "1m", 0..5)])
+            .await
+            .unwrap();
+        // ETHUSDT was delisted or failed, BTCUSDT moved on by three candles.
+        storage
+            .save_klines(&[series("BTCUSDT", "1m", 3..8)])
+            .await
+            .unwrap();
+
+        let stored = storage.load_candles("1m", 0).await.unwrap();
+        let symbols: Vec<&str> = stored.iter().map(|s| s.symbol.as_str()).collect();
+        assert_eq!(symbols, ["BTCUSDT"]);
+        assert_eq!(open_times(&stored[0]), [3, 4, 5, 6, 7]);
+
+        // Switching interval drops the candles of the old one.
+        storage
+            .save_klines(&[series("BTCUSDT", "1h", 0..2)])
+            .await
+            .unwrap();
+        assert!(storage.load_candles("1m", 0).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn series_are_visited_until_the_visitor_fails() {
+        let db = TempDb::open("visit").await;
+        let storage = &db.storage;
+        storage
+            .save_klines(&[series("BTCUSDT", "1m", 0..3), series("ETHUSDT", "1m", 0..3)])
+            .await
+            .unwrap();
+
+        let mut visited = Vec::new();
+        storage
+            .for_each_series(&mut |s| {
+                visited.push(s.symbol);
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(visited, ["BTCUSDT", "ETHUSDT"]);
+
+        let error = storage
+            .for_each_series(&mut |_| Err(anyhow::anyhow!("full")))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "full");
+    }
+}
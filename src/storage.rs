@@ -0,0 +1,356 @@
+//! Where the pipeline keeps what it downloads and computes. The JSON backend
+//! writes one file per dataset through [`AsyncStorageManager`]; the SQLite
+//! backend keeps candles in a table so they can be appended and read by range.
+//!
+//! Snapshots of past results live with the rest: as `snapshots/*.json` files
+//! or in a table. The configuration and exports stay plain files with either
+//! backend.
+
+use crate::find_tickers::ExchangeInfo;
+use crate::klines::{FetchFailure, KlineResult};
+use crate::sqlite_storage::SqliteStorage;
+use crate::storage_utils::{AppConfig, AsyncStorageManager};
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use serde::de::{self, DeserializeOwned, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::io::BufReader;
+use tokio::sync::mpsc;
+
+/// Runs kept in `runs.json` by the JSON backend.
+const MAX_JSON_RUNS: usize = 100;
+
+/// Where the JSON backend keeps snapshots, one `results_<taken_at>.json` each.
+const SNAPSHOT_DIR: &str = "snapshots";
+const SNAPSHOT_PREFIX: &str = "results_";
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    Json,
+    Sqlite,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunOutcome {
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+/// What one pipeline run did.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunMetadata {
+    pub started_at: i64,
+    pub finished_at: i64,
+    pub outcome: RunOutcome,
+    /// Symbols whose klines were downloaded.
+    pub symbols: usize,
+    /// Symbols whose klines could not be downloaded.
+    pub failures: usize,
+}
+
+pub type SeriesVisitor<'a> = dyn FnMut(KlineResult) -> Result<()> + Send + 'a;
+
+/// Runs `read` on the blocking pool and hands every series it sends to
+/// `visit` on the calling task. Sending fails once `visit` has failed, which
+/// stops `read`; the error from `visit` is the one returned.
+pub(crate) async fn visit_blocking(
+    read: impl FnOnce(&mut dyn FnMut(KlineResult) -> Result<()>) -> Result<()> + Send + 'static,
+    visit: &mut SeriesVisitor<'_>,
+) -> Result<()> {
+    let (tx, mut rx) = mpsc::channel(1);
+    let reading = tokio::task::spawn_blocking(move || {
+        read(&mut |series| {
+            tx.blocking_send(series)
+                .map_err(|_| anyhow!("Series visitor stopped"))
+        })
+    });
+
+    let mut visited = Ok(());
+    while let Some(series) = rx.recv().await {
+        visited = visit(series);
+        if visited.is_err() {
+            break;
+        }
+    }
+    drop(rx);
+    let read = reading.await?;
+    visited.and(read)
+}
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Symbols listed by one source, keyed by [`exchange_info_key`](crate::market_data::exchange_info_key).
+    async fn save_exchange_info(&self, key: &str, info: &ExchangeInfo) -> Result<()>;
+    async fn load_exchange_info(&self, key: &str) -> Result<ExchangeInfo>;
+
+    /// Replaces the current candle series of every symbol.
+    async fn save_klines(&self, series: &[KlineResult]) -> Result<()>;
+    async fn load_klines(&self) -> Result<Vec<KlineResult>>;
+    /// Hands the stored series to `visit` one at a time, so only one symbol's
+    /// candles are in memory at once. Stops at the first error `visit` returns.
+    /// Nothing is visited when no candles are stored.
+    async fn for_each_series(&self, visit: &mut SeriesVisitor<'_>) -> Result<()>;
+    /// Stored candles of `interval` opened at or after `since`, per symbol.
+    /// Backends that keep more than the current series may return older ones too.
+    async fn load_candles(&self, interval: &str, since: i64) -> Result<Vec<KlineResult>>;
+
+    async fn save_fetch_errors(&self, failures: &[FetchFailure]) -> Result<()>;
+    async fn load_fetch_errors(&self) -> Result<Vec<FetchFailure>>;
+
+    async fn save_results_value(&self, results: Value) -> Result<()>;
+    async fn load_results_value(&self) -> Result<Value>;
+
+    async fn record_run(&self, run: &RunMetadata) -> Result<()>;
+
+    /// Results of the run finished at `taken_at`, kept for the history view.
+    async fn save_snapshot_value(&self, taken_at: i64, results: Value) -> Result<()>;
+    async fn load_snapshot_value(&self, taken_at: i64) -> Result<Value>;
+    /// When each stored snapshot was taken, in no particular order.
+    async fn list_snapshots(&self) -> Result<Vec<i64>>;
+    async fn delete_snapshot(&self, taken_at: i64) -> Result<()>;
+}
+
+impl dyn Storage + '_ {
+    pub async fn save_results<T: Serialize>(&self, results: &T) -> Result<()> {
+        self.save_results_value(serde_json::to_value(results)?)
+            .await
+    }
+
+    pub async fn load_results<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(serde_json::from_value(self.load_results_value().await?)?)
+    }
+
+    pub async fn save_snapshot<T: Serialize>(&self, taken_at: i64, results: &T) -> Result<()> {
+        self.save_snapshot_value(taken_at, serde_json::to_value(results)?)
+            .await
+    }
+
+    pub async fn load_snapshot<T: DeserializeOwned>(&self, taken_at: i64) -> Result<T> {
+        Ok(serde_json::from_value(
+            self.load_snapshot_value(taken_at).await?,
+        )?)
+    }
+}
+
+/// Opens the backend selected in the configuration.
+pub async fn open(app_config: &AppConfig) -> Result<Box<dyn Storage>> {
+    let files = AsyncStorageManager::open().await?;
+    Ok(match app_config.storage {
+        StorageBackend::Json => Box::new(JsonStorage::new(files)),
+        StorageBackend::Sqlite => Box::new(SqliteStorage::open(&files.base_dir).await?),
+    })
+}
+
+// JSON BACKEND
+
+/// Walks the top-level array of `klines.json`, deserializing one series at a
+/// time. An error from `send` is kept aside so it reaches the caller intact.
+struct EachSeries<'s> {
+    send: &'s mut dyn FnMut(KlineResult) -> Result<()>,
+    stopped: Option<anyhow::Error>,
+}
+
+impl<'de> Visitor<'de> for &mut EachSeries<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of kline series")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(series) = seq.next_element::<KlineResult>()? {
+            if let Err(e) = (self.send)(series) {
+                self.stopped = Some(e);
+                return Err(de::Error::custom("stopped by visitor"));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct JsonStorage {
+    files: AsyncStorageManager,
+}
+
+impl JsonStorage {
+    pub fn new(files: AsyncStorageManager) -> Self {
+        Self { files }
+    }
+
+    async fn snapshot_files(&self) -> Result<AsyncStorageManager> {
+        AsyncStorageManager::new_in(self.files.base_dir.join(SNAPSHOT_DIR)).await
+    }
+}
+
+fn snapshot_name(taken_at: i64) -> String {
+    format!("{}{}", SNAPSHOT_PREFIX, taken_at)
+}
+
+#[async_trait]
+impl Storage for JsonStorage {
+    async fn save_exchange_info(&self, key: &str, info: &ExchangeInfo) -> Result<()> {
+        self.files.save(key, info).await
+    }
+
+    async fn load_exchange_info(&self, key: &str) -> Result<ExchangeInfo> {
+        self.files.load(key).await
+    }
+
+    async fn save_klines(&self, series: &[KlineResult]) -> Result<()> {
+        self.files.save("klines", &series).await
+    }
+
+    async fn load_klines(&self) -> Result<Vec<KlineResult>> {
+        self.files.load("klines").await
+    }
+
+    async fn for_each_series(&self, visit: &mut SeriesVisitor<'_>) -> Result<()> {
+        let path = self.files.base_dir.join("klines.json");
+        let read = move |send: &mut dyn FnMut(KlineResult) -> Result<()>| {
+            let file = match std::fs::File::open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => bail!("Could not read {}: {}", path.display(), e),
+            };
+            let mut each = EachSeries {
+                send,
+                stopped: None,
+            };
+            let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(file));
+            let parsed = (&mut deserializer).deserialize_seq(&mut each);
+            if let Some(e) = each.stopped {
+                return Err(e);
+            }
+            parsed?;
+            deserializer.end()?;
+            Ok(())
+        };
+        visit_blocking(read, visit).await
+    }
+
+    async fn load_candles(&self, interval: &str, since: i64) -> Result<Vec<KlineResult>> {
+        let mut series: Vec<KlineResult> = self.load_klines().await?;
+        series.retain(|s| s.interval == interval);
+        for s in &mut series {
+            s.klines.retain(|k| k.open_time >= since);
+        }
+        Ok(series)
+    }
+
+    async fn save_fetch_errors(&self, failures: &[FetchFailure]) -> Result<()> {
+        self.files.save("fetch_errors", &failures).await
+    }
+
+    async fn load_fetch_errors(&self) -> Result<Vec<FetchFailure>> {
+        self.files.load("fetch_errors").await
+    }
+
+    async fn save_results_value(&self, results: Value) -> Result<()> {
+        self.files.save("results", &results).await
+    }
+
+    async fn load_results_value(&self) -> Result<Value> {
+        self.files.load("results").await
+    }
+
+    async fn record_run(&self, run: &RunMetadata) -> Result<()> {
+        let mut runs: Vec<RunMetadata> = self.files.load("runs").await.unwrap_or_default();
+        runs.push(run.clone());
+        let excess = runs.len().saturating_sub(MAX_JSON_RUNS);
+        runs.drain(..excess);
+        self.files.save("runs", &runs).await
+    }
+
+    async fn save_snapshot_value(&self, taken_at: i64, results: Value) -> Result<()> {
+        self.snapshot_files()
+            .await?
+            .save(&snapshot_name(taken_at), &results)
+            .await
+    }
+
+    async fn load_snapshot_value(&self, taken_at: i64) -> Result<Value> {
+        self.snapshot_files()
+            .await?
+            .load(&snapshot_name(taken_at))
+            .await
+    }
+
+    async fn list_snapshots(&self) -> Result<Vec<i64>> {
+        let files = self.snapshot_files().await?;
+        let mut snapshots = Vec::new();
+        let mut entries = tokio::fs::read_dir(&files.base_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            if let Some(taken_at) = file_name
+                .to_str()
+                .and_then(|name| name.strip_prefix(SNAPSHOT_PREFIX))
+                .and_then(|name| name.strip_suffix(".json"))
+                .and_then(|stamp| stamp.parse().ok())
+            {
+                snapshots.push(taken_at);
+            }
+        }
+        Ok(snapshots)
+    }
+
+    async fn delete_snapshot(&self, taken_at: i64) -> Result<()> {
+        let files = self.snapshot_files().await?;
+        let path = files
+            .base_dir
+            .join(format!("{}.json", snapshot_name(taken_at)));
+        Ok(tokio::fs::remove_file(path).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::klines::Kline;
+    use crate::storage_utils::{Exchange, Market};
+
+    fn series(symbol: &str) -> KlineResult {
+        KlineResult {
+            symbol: symbol.to_string(),
+            exchange: Exchange::Binance,
+            market: Market::UsdM,
+            interval: "1m".to_string(),
+            underlying_sub_type: Vec::new(),
+            klines: vec![Kline::flat(0, 1.0, 60_000)],
+        }
+    }
+
+    #[tokio::test]
+    async fn json_series_are_streamed_to_the_visitor() {
+        let dir = std::env::temp_dir().join(format!("screener-json-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let storage = JsonStorage::new(AsyncStorageManager::new_in(dir.clone()).await.unwrap());
+
+        let mut visited = Vec::new();
+        let mut collect = |s: KlineResult| {
+            visited.push(s.symbol);
+            Ok(())
+        };
+        // Nothing is visited before any klines are stored.
+        storage.for_each_series(&mut collect).await.unwrap();
+        storage
+            .save_klines(&[series("BTCUSDT"), series("ETHUSDT")])
+            .await
+            .unwrap();
+        storage.for_each_series(&mut collect).await.unwrap();
+        assert_eq!(visited, ["BTCUSDT", "ETHUSDT"]);
+
+        let error = storage
+            .for_each_series(&mut |_| Err(anyhow!("full")))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "full");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
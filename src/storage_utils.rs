@@ -1,6 +1,10 @@
-use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use crate::export::ExportFormat;
+use crate::indicators::{IndicatorConfig, IndicatorRegistry, default_rsi_period};
+use crate::storage::StorageBackend;
+use serde::{Deserialize, Deserializer, Serialize, de::DeserializeOwned};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use tokio::fs;
 
 // --- CONFIGURATION STRUCTS ---
@@ -9,36 +13,367 @@ use tokio::fs;
 pub struct KlineConfig {
     pub limit: u32,
     pub interval: String,
+    /// Reach back to this date (`YYYY-MM-DD` or RFC 3339) instead of keeping
+    /// the latest `limit` candles.
+    #[serde(default)]
+    pub start_date: Option<String>,
+}
+
+/// How failed kline and exchange info requests are retried.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Total attempts per request, the first one included.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every retry after it.
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    /// Fraction of each delay that is randomized, from 0 (none) to 1 (full jitter).
+    pub jitter: f64,
+    /// HTTP statuses worth another attempt. Network errors are always retried.
+    pub retry_statuses: Vec<u16>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+            jitter: 0.5,
+            retry_statuses: vec![408, 429, 500, 502, 503, 504],
+        }
+    }
+}
+
+/// Which snapshots of past runs are kept. Both limits apply when both are set.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    /// Keep at most this many snapshots.
+    pub keep_count: Option<usize>,
+    /// Drop snapshots older than this.
+    pub max_age_hours: Option<u64>,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            keep_count: Some(500),
+            max_age_hours: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Exchange {
+    #[default]
+    Binance,
+    Bybit,
+    Okx,
+}
+
+impl Exchange {
+    /// Stable identifier used in storage file names.
+    pub fn id(self) -> &'static str {
+        match self {
+            Exchange::Binance => "binance",
+            Exchange::Bybit => "bybit",
+            Exchange::Okx => "okx",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Exchange::Binance => "Binance",
+            Exchange::Bybit => "Bybit",
+            Exchange::Okx => "OKX",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Market {
+    #[default]
+    #[serde(rename = "usdt_m")]
+    UsdM,
+    #[serde(rename = "coin_m")]
+    CoinM,
+    #[serde(rename = "spot")]
+    Spot,
+}
+
+impl Market {
+    /// Stable identifier used in storage file names.
+    pub fn id(self) -> &'static str {
+        match self {
+            Market::UsdM => "usdt_m",
+            Market::CoinM => "coin_m",
+            Market::Spot => "spot",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Market::UsdM => "USDT-M",
+            Market::CoinM => "COIN-M",
+            Market::Spot => "Spot",
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AppConfig {
+    #[serde(default)]
+    pub exchange: Exchange,
+
+    /// One market or a list of markets to screen side by side.
+    #[serde(default = "default_markets", deserialize_with = "one_or_many")]
+    pub market: Vec<Market>,
+
     pub klines: KlineConfig,
 
     #[serde(default)]
     pub filters: HashMap<String, String>,
 
+    /// Per-market filters, used instead of `filters` for the given market.
+    #[serde(default)]
+    pub market_filters: HashMap<Market, HashMap<String, String>>,
+
+    /// Per-exchange filters by market, used instead of `market_filters` and
+    /// `filters` on that exchange. Bybit and OKX report no `underlyingType`,
+    /// so they get their own filters by default.
+    #[serde(default = "default_exchange_filters")]
+    pub exchange_filters: HashMap<Exchange, HashMap<Market, HashMap<String, String>>>,
+
+    /// Period of the RSI computed when `indicators` is not set.
     #[serde(default = "default_rsi_period")]
     pub rsi_period: u32,
+
+    /// Indicators computed for every symbol, in display order.
+    #[serde(default)]
+    pub indicators: Option<Vec<IndicatorConfig>>,
+
+    /// Keep the results live from kline streams instead of refreshing on F5.
+    #[serde(default)]
+    pub stream: bool,
+
+    /// WebSocket base URL per market, replacing the exchange's own, e.g. to
+    /// stream from a local stand-in.
+    #[serde(default)]
+    pub stream_urls: HashMap<Market, String>,
+
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// Format of the files the TUI export key writes.
+    #[serde(default)]
+    pub export_format: ExportFormat,
+
+    #[serde(default)]
+    pub snapshots: SnapshotConfig,
+
+    /// Where downloaded candles, errors and results are kept.
+    #[serde(default)]
+    pub storage: StorageBackend,
+}
+
+impl AppConfig {
+    pub fn filters_for(&self, exchange: Exchange, market: Market) -> &HashMap<String, String> {
+        self.exchange_filters
+            .get(&exchange)
+            .and_then(|by_market| by_market.get(&market))
+            .or_else(|| self.market_filters.get(&market))
+            .unwrap_or(&self.filters)
+    }
+
+    pub fn indicator_registry(&self) -> anyhow::Result<IndicatorRegistry> {
+        match &self.indicators {
+            Some(configs) => IndicatorRegistry::from_config(configs),
+            None => IndicatorRegistry::from_config(&IndicatorConfig::defaults(self.rsi_period)),
+        }
+    }
+}
+
+fn default_markets() -> Vec<Market> {
+    vec![Market::UsdM]
+}
+
+/// Trading USDT perpetuals on the venues whose symbols lack Binance's
+/// `underlyingType`.
+fn default_exchange_filters() -> HashMap<Exchange, HashMap<Market, HashMap<String, String>>> {
+    let perpetuals: HashMap<String, String> = [
+        ("status", "TRADING"),
+        ("contractType", "PERPETUAL"),
+        ("quoteAsset", "USDT"),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value.to_string()))
+    .collect();
+    [Exchange::Bybit, Exchange::Okx]
+        .into_iter()
+        .map(|exchange| {
+            (
+                exchange,
+                HashMap::from([(Market::UsdM, perpetuals.clone())]),
+            )
+        })
+        .collect()
 }
 
-fn default_rsi_period() -> u32 {
-    14
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<Market>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(Market),
+        Many(Vec<Market>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(market) => vec![market],
+        OneOrMany::Many(markets) => markets,
+    })
 }
 
 // STORAGE MANAGER
+
+/// Directory name under the platform's data and config directories.
+const APP_DIR: &str = "comfy-screener";
+/// Directory holding everything next to the binary, used when the platform
+/// directories cannot be determined.
+const STORAGE_DIR: &str = "storage";
+const CONFIG_FILE: &str = "config.json";
+
+/// Overrides the data directory when `--storage-dir` is not given.
+pub const STORAGE_DIR_ENV: &str = "COMFY_SCREENER_STORAGE_DIR";
+/// Overrides the configuration file when `--config` is not given.
+pub const CONFIG_PATH_ENV: &str = "COMFY_SCREENER_CONFIG";
+
+/// Written to the configuration path on first run.
+pub(crate) const DEFAULT_CONFIG: &str = include_str!("../config.json");
+
+/// Set once at startup from `--storage-dir`.
+static STORAGE_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+/// Set once at startup from `--config`.
+static CONFIG_PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Points every storage manager opened afterwards at `dir`.
+pub fn set_storage_dir(dir: PathBuf) {
+    let _ = STORAGE_DIR_OVERRIDE.set(dir);
+}
+
+/// Reads the configuration from `path` instead of the default location.
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_PATH_OVERRIDE.set(path);
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+fn exe_relative(relative_path: impl AsRef<Path>) -> anyhow::Result<PathBuf> {
+    let exe_path = std::env::current_exe()?;
+    Ok(exe_path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Could not find binary directory"))?
+        .join(relative_path))
+}
+
+/// Where downloaded and computed data is kept: `--storage-dir`, then
+/// `$COMFY_SCREENER_STORAGE_DIR`, then the platform data directory
+/// (`$XDG_DATA_HOME/comfy-screener` on Linux), then `storage/` next to the binary.
+pub fn data_dir() -> anyhow::Result<PathBuf> {
+    if let Some(dir) = STORAGE_DIR_OVERRIDE.get() {
+        return Ok(dir.clone());
+    }
+    if let Some(dir) = env_path(STORAGE_DIR_ENV) {
+        return Ok(dir);
+    }
+    match dirs::data_dir() {
+        Some(dir) => Ok(dir.join(APP_DIR)),
+        None => exe_relative(STORAGE_DIR),
+    }
+}
+
+/// The configuration file: `--config`, then `$COMFY_SCREENER_CONFIG`, then
+/// `config.json` in the platform config directory (`$XDG_CONFIG_HOME/comfy-screener`
+/// on Linux), then `storage/config.json` next to the binary.
+pub fn config_path() -> anyhow::Result<PathBuf> {
+    if let Some(path) = CONFIG_PATH_OVERRIDE.get() {
+        return Ok(path.clone());
+    }
+    if let Some(path) = env_path(CONFIG_PATH_ENV) {
+        return Ok(path);
+    }
+    match dirs::config_dir() {
+        Some(dir) => Ok(dir.join(APP_DIR).join(CONFIG_FILE)),
+        None => legacy_config_path(),
+    }
+}
+
+/// Where the configuration was kept before the platform config directory was used.
+fn legacy_config_path() -> anyhow::Result<PathBuf> {
+    exe_relative(Path::new(STORAGE_DIR).join(CONFIG_FILE))
+}
+
+/// Creates [`config_path`] when it is missing, unless it was named with
+/// `--config`: copied from the legacy `storage/config.json` next to the binary
+/// when there is one, otherwise with the defaults. Returns a message saying
+/// which file was written, for the caller to show where it fits its output.
+pub async fn ensure_config() -> anyhow::Result<Option<String>> {
+    let path = config_path()?;
+    if CONFIG_PATH_OVERRIDE.get().is_some() || fs::try_exists(&path).await? {
+        return Ok(None);
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+
+    let legacy = legacy_config_path()?;
+    if legacy != path && fs::try_exists(&legacy).await? {
+        fs::copy(&legacy, &path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Could not copy config {}: {}", legacy.display(), e))?;
+        return Ok(Some(format!(
+            "Copied config {} to {}, which is used from now on",
+            legacy.display(),
+            path.display()
+        )));
+    }
+    fs::write(&path, DEFAULT_CONFIG)
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not create config {}: {}", path.display(), e))?;
+    Ok(Some(format!("Created default config {}", path.display())))
+}
+
+/// Loads the application configuration from [`config_path`], which
+/// [`ensure_config`] has created if needed.
+pub async fn load_config() -> anyhow::Result<AppConfig> {
+    let path = config_path()?;
+    let content = fs::read(&path)
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not read config {}: {}", path.display(), e))?;
+    serde_json::from_slice(&content)
+        .map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))
+}
+
 pub struct AsyncStorageManager {
     pub base_dir: PathBuf,
 }
 
 impl AsyncStorageManager {
-    pub async fn new_relative<P: AsRef<Path>>(relative_path: P) -> anyhow::Result<Self> {
-        let exe_path = std::env::current_exe()?;
-        let base_dir = exe_path
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("Could not find binary directory"))?
-            .join(relative_path);
+    /// Opens the application's data directory, see [`data_dir`].
+    pub async fn open() -> anyhow::Result<Self> {
+        Self::new_in(data_dir()?).await
+    }
 
+    pub async fn new_in(base_dir: PathBuf) -> anyhow::Result<Self> {
         if !base_dir.exists() {
             fs::create_dir_all(&base_dir).await?;
         }
@@ -61,7 +396,9 @@ impl AsyncStorageManager {
 
     pub async fn load<T: DeserializeOwned>(&self, filename: &str) -> anyhow::Result<T> {
         let path = self.base_dir.join(format!("{}.json", filename));
-        let content = fs::read(path).await?;
+        let content = fs::read(&path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Could not read {}: {}", path.display(), e))?;
         let data = serde_json::from_slice(&content)?;
         Ok(data)
     }
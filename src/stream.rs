@@ -0,0 +1,437 @@
+//! Live kline streaming over Binance combined WebSocket streams.
+//!
+//! History is seeded once through the REST pipeline. After that every closed
+//! candle updates the movement and indicators of its symbol, and `results.json` is
+//! rewritten so the TUI can pick up the new ranking without an F5 refresh.
+
+use crate::analysis::{self, Cancelled};
+use crate::binance;
+use crate::cumulative_price_change::{self, ResultItem};
+use crate::klines::{self, Kline, KlineResult};
+use crate::progress::ProgressReporter;
+use crate::storage;
+use crate::storage_utils::{self, Exchange, Market};
+use anyhow::{Result, bail};
+use futures::StreamExt;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio_util::sync::CancellationToken;
+
+/// Binance caps a single futures connection at 200 streams.
+const MAX_STREAMS_PER_CONNECTION: usize = 200;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Candles of all symbols close at the same moment, so updates arriving within
+/// this window are folded into one rewrite of the results.
+const COALESCE_WINDOW: Duration = Duration::from_millis(500);
+
+// DATA
+
+#[derive(Deserialize)]
+struct CombinedEvent {
+    data: KlineEvent,
+}
+
+#[derive(Deserialize)]
+struct KlineEvent {
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "k")]
+    kline: StreamKline,
+}
+
+#[derive(Deserialize)]
+struct StreamKline {
+    #[serde(rename = "t")]
+    open_time: i64,
+    #[serde(rename = "T")]
+    close_time: i64,
+    #[serde(rename = "o")]
+    open: String,
+    #[serde(rename = "h")]
+    high: String,
+    #[serde(rename = "l")]
+    low: String,
+    #[serde(rename = "c")]
+    close: String,
+    #[serde(rename = "v")]
+    volume: String,
+    #[serde(rename = "q")]
+    quote_volume: String,
+    #[serde(rename = "n")]
+    trades: u64,
+    #[serde(rename = "V")]
+    taker_buy_base_volume: String,
+    #[serde(rename = "Q")]
+    taker_buy_quote_volume: String,
+    #[serde(rename = "x")]
+    is_closed: bool,
+}
+
+/// A candle that has just closed on one of the subscribed streams.
+pub struct ClosedCandle {
+    pub market: Market,
+    pub symbol: String,
+    pub kline: Kline,
+}
+
+// STREAM CLIENT
+
+/// Builds the combined stream URL for the given symbols.
+pub fn stream_url(base_url: &str, symbols: &[String], interval: &str) -> String {
+    let streams: Vec<String> = symbols
+        .iter()
+        .map(|s| format!("{}@kline_{}", s.to_lowercase(), interval))
+        .collect();
+    format!(
+        "{}/stream?streams={}",
+        base_url.trim_end_matches('/'),
+        streams.join("/")
+    )
+}
+
+/// Parses a combined stream message, keeping only closed candles.
+pub fn parse_event(text: &str, market: Market) -> Option<ClosedCandle> {
+    let event: CombinedEvent = serde_json::from_str(text).ok()?;
+    let kline = event.data.kline;
+    if !kline.is_closed {
+        return None;
+    }
+
+    Some(ClosedCandle {
+        market,
+        symbol: event.data.symbol,
+        kline: Kline {
+            open_time: kline.open_time,
+            open: kline.open.parse().ok()?,
+            high: kline.high.parse().ok()?,
+            low: kline.low.parse().ok()?,
+            close: kline.close.parse().ok()?,
+            volume: kline.volume.parse().ok()?,
+            close_time: kline.close_time,
+            quote_volume: kline.quote_volume.parse().ok()?,
+            trades: Some(kline.trades),
+            taker_buy_base_volume: kline.taker_buy_base_volume.parse().ok(),
+            taker_buy_quote_volume: kline.taker_buy_quote_volume.parse().ok(),
+        },
+    })
+}
+
+/// The wait before the next reconnect, doubling up to [`MAX_BACKOFF`].
+fn next_backoff(backoff: Duration) -> Duration {
+    (backoff * 2).min(MAX_BACKOFF)
+}
+
+/// Forwards closed candles from `url` until the receiving side goes away,
+/// reconnecting with exponential backoff whenever the connection drops.
+pub async fn subscribe(url: String, market: Market, tx: mpsc::Sender<ClosedCandle>) {
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        if let Ok((mut ws, _)) = connect_async(url.as_str()).await {
+            backoff = INITIAL_BACKOFF;
+            while let Some(message) = ws.next().await {
+                match message {
+                    Ok(Message::Text(text)) => {
+                        if let Some(candle) = parse_event(&text, market)
+                            && tx.send(candle).await.is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Ok(Message::Close(_)) | Err(_) => break,
+                    _ => {}
+                }
+            }
+        }
+
+        if tx.is_closed() {
+            return;
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = next_backoff(backoff);
+    }
+}
+
+// SERIES UPDATES
+
+/// Appends a closed candle to its series, replacing the seeded copy of the same
+/// candle if there is one, and trims the series to `limit` candles.
+fn apply_candle(series: &mut KlineResult, kline: Kline, limit: usize) {
+    match series.klines.last() {
+        Some(last) if last.close_time == kline.close_time => {
+            *series.klines.last_mut().unwrap() = kline;
+        }
+        Some(last) if last.close_time > kline.close_time => return,
+        _ => series.klines.push(kline),
+    }
+
+    if series.klines.len() > limit {
+        let excess = series.klines.len() - limit;
+        series.klines.drain(..excess);
+    }
+}
+
+// MAIN
+
+/// Seeds history over REST, then keeps `results.json` up to date from the
+/// kline streams. A message is sent on `updates` every time the results change;
+/// the stream stops once that channel is closed, or with [`Cancelled`] once
+/// `cancel` fires. Seeding reports on `progress`.
+pub async fn run(
+    updates: mpsc::Sender<()>,
+    progress: ProgressReporter,
+    cancel: CancellationToken,
+) -> Result<()> {
+    let app_config = storage_utils::load_config().await?;
+    if app_config.exchange != Exchange::Binance {
+        bail!("Live streaming is only available for Binance");
+    }
+
+    analysis::run_analysis_pipeline(&progress, &cancel).await?;
+    if updates.send(()).await.is_err() {
+        return Ok(());
+    }
+
+    let storage = storage::open(&app_config).await?;
+    let registry = app_config.indicator_registry()?;
+
+    let seeded = storage.load_klines().await?;
+    let limit =
+        klines::window_len(&app_config.klines, chrono::Utc::now().timestamp_millis())? as usize;
+
+    let mut series: HashMap<(Market, String), KlineResult> = HashMap::new();
+    let mut results: HashMap<(Market, String), (ResultItem, i64)> = HashMap::new();
+    for symbol_data in seeded {
+        let key = (symbol_data.market, symbol_data.symbol.clone());
+        if let Some(result) = cumulative_price_change::analyze_symbol(&symbol_data, &registry) {
+            results.insert(key.clone(), result);
+        }
+        series.insert(key, symbol_data);
+    }
+
+    let (candle_tx, mut candle_rx) = mpsc::channel::<ClosedCandle>(1024);
+    for &market in &app_config.market {
+        let base_url = app_config
+            .stream_urls
+            .get(&market)
+            .map_or(binance::stream_base_url(market), String::as_str);
+        let symbols: Vec<String> = series
+            .keys()
+            .filter(|(m, _)| *m == market)
+            .map(|(_, symbol)| symbol.clone())
+            .collect();
+        for chunk in symbols.chunks(MAX_STREAMS_PER_CONNECTION) {
+            let url = stream_url(base_url, chunk, &app_config.klines.interval);
+            tokio::spawn(subscribe(url, market, candle_tx.clone()));
+        }
+    }
+    drop(candle_tx);
+
+    loop {
+        let first = tokio::select! {
+            candle = candle_rx.recv() => match candle {
+                Some(candle) => candle,
+                None => break,
+            },
+            _ = cancel.cancelled() => return Err(Cancelled.into()),
+        };
+        tokio::time::sleep(COALESCE_WINDOW).await;
+        let mut closed = vec![first];
+        while let Ok(candle) = candle_rx.try_recv() {
+            closed.push(candle);
+        }
+
+        for candle in closed {
+            let key = (candle.market, candle.symbol);
+            let Some(symbol_data) = series.get_mut(&key) else {
+                continue;
+            };
+            apply_candle(symbol_data, candle.kline, limit);
+            match cumulative_price_change::analyze_symbol(symbol_data, &registry) {
+                Some(result) => results.insert(key, result),
+                None => results.remove(&key),
+            };
+        }
+
+        let last_updated = results.values().map(|(_, t)| *t).max().unwrap_or(0);
+        let items = results.values().map(|(item, _)| item.clone()).collect();
+        if let Some(output_data) = cumulative_price_change::build_output(items, last_updated) {
+            storage.save_results(&output_data).await?;
+        }
+
+        if updates.send(()).await.is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::SinkExt;
+    use tokio::net::TcpListener;
+    use tokio::time::timeout;
+    use tokio_tungstenite::accept_hdr_async;
+    use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+
+    const MINUTE: i64 = 60_000;
+
+    /// A combined stream kline message as Binance sends it.
+    fn kline_message(open_time: i64, close: f64, closed: bool) -> String {
+        serde_json::json!({
+            "stream": "btcusdt@kline_1m",
+            "data": {
+                "e": "kline",
+                "E": open_time + MINUTE,
+                "s": "BTCUSDT",
+                "k": {
+                    "t": open_time,
+                    "T": open_time + MINUTE - 1,
+                    "s": "BTCUSDT",
+                    "i": "1m",
+                    "o": "100.0",
+                    "c": close.to_string(),
+                    "h": "110.0",
+                    "l": "90.0",
+                    "v": "12.5",
+                    "n": 42,
+                    "x": closed,
+                    "q": "1250.0",
+                    "V": "6.0",
+                    "Q": "600.0",
+                    "B": "0"
+                }
+            }
+        })
+        .to_string()
+    }
+
+    fn series(klines: Vec<Kline>) -> KlineResult {
+        KlineResult {
+            symbol: "BTCUSDT".to_string(),
+            exchange: Exchange::Binance,
+            market: Market::UsdM,
+            interval: "1m".to_string(),
+            underlying_sub_type: Vec::new(),
+            klines,
+        }
+    }
+
+    #[test]
+    fn stream_url_lists_lowercase_streams() {
+        let symbols = ["BTCUSDT".to_string(), "ETHUSDT".to_string()];
+        assert_eq!(
+            stream_url("wss://fstream.binance.com/", &symbols, "1m"),
+            "wss://fstream.binance.com/stream?streams=btcusdt@kline_1m/ethusdt@kline_1m"
+        );
+    }
+
+    #[test]
+    fn only_closed_candles_are_parsed() {
+        assert!(parse_event(&kline_message(0, 101.0, false), Market::UsdM).is_none());
+        assert!(parse_event("not json", Market::UsdM).is_none());
+
+        let closed = parse_event(&kline_message(0, 101.0, true), Market::CoinM).unwrap();
+        assert_eq!(closed.symbol, "BTCUSDT");
+        assert_eq!(closed.market, Market::CoinM);
+        assert_eq!(
+            closed.kline,
+            Kline {
+                open_time: 0,
+                open: 100.0,
+                high: 110.0,
+                low: 90.0,
+                close: 101.0,
+                volume: 12.5,
+                close_time: MINUTE - 1,
+                quote_volume: 1250.0,
+                trades: Some(42),
+                taker_buy_base_volume: Some(6.0),
+                taker_buy_quote_volume: Some(600.0),
+            }
+        );
+    }
+
+    #[test]
+    fn applied_candles_replace_append_and_trim() {
+        let candle = |open_time, close| Kline::flat(open_time, close, MINUTE);
+        let mut s = series(vec![candle(0, 1.0), candle(MINUTE, 2.0)]);
+
+        // The seeded copy of the same candle is replaced.
+        apply_candle(&mut s, candle(MINUTE, 3.0), 2);
+        assert_eq!(s.klines, [candle(0, 1.0), candle(MINUTE, 3.0)]);
+
+        // Candles older than the last one are ignored.
+        apply_candle(&mut s, candle(0, 9.0), 2);
+        assert_eq!(s.klines, [candle(0, 1.0), candle(MINUTE, 3.0)]);
+
+        // New candles are appended and the oldest dropped past the limit.
+        apply_candle(&mut s, candle(2 * MINUTE, 4.0), 2);
+        assert_eq!(s.klines, [candle(MINUTE, 3.0), candle(2 * MINUTE, 4.0)]);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut waits = Vec::new();
+        for _ in 0..8 {
+            waits.push(backoff.as_secs());
+            backoff = next_backoff(backoff);
+        }
+        assert_eq!(waits, [1, 2, 4, 8, 16, 32, 60, 60]);
+    }
+
+    // The handshake callback's error type is tungstenite's, not ours.
+    #[allow(clippy::result_large_err)]
+    #[tokio::test]
+    async fn subscribe_reconnects_and_forwards_closed_candles() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("ws://{}", listener.local_addr().unwrap());
+
+        // Each connection sends one candle update and its close, then drops.
+        let server = tokio::spawn(async move {
+            let mut paths = Vec::new();
+            for open_time in [0, MINUTE] {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut path = String::new();
+                let mut ws = accept_hdr_async(stream, |request: &Request, response: Response| {
+                    path = request.uri().to_string();
+                    Ok(response)
+                })
+                .await
+                .unwrap();
+                for closed in [false, true] {
+                    let message = kline_message(open_time, 101.0, closed);
+                    ws.send(Message::Text(message)).await.unwrap();
+                }
+                ws.close(None).await.unwrap();
+                paths.push(path);
+            }
+            paths
+        });
+
+        let (tx, mut rx) = mpsc::channel(8);
+        let url = stream_url(&base_url, &["BTCUSDT".to_string()], "1m");
+        let client = tokio::spawn(subscribe(url, Market::UsdM, tx));
+
+        let wait = Duration::from_secs(10);
+        let first = timeout(wait, rx.recv()).await.unwrap().unwrap();
+        let second = timeout(wait, rx.recv()).await.unwrap().unwrap();
+        assert_eq!(first.kline.open_time, 0);
+        assert_eq!(second.kline.open_time, MINUTE);
+        assert_eq!(
+            server.await.unwrap(),
+            ["/stream?streams=btcusdt@kline_1m"; 2]
+        );
+
+        // Once nobody listens, the subscription stops instead of reconnecting.
+        drop(rx);
+        timeout(wait, client).await.unwrap().unwrap();
+    }
+}
@@ -8,80 +8,313 @@ use crossterm::{
 use ratatui::{
     prelude::*,
     text::Line,
-    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table},
+    widgets::{Block, Borders, Cell, Clear, Gauge, Paragraph, Row, Table},
 };
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
 use std::io;
 use std::time::Duration;
 use tokio::sync::mpsc;
-
-use crate::analysis;
-use crate::storage_utils::AsyncStorageManager;
+use tokio_util::sync::CancellationToken;
+
+use crate::analysis::{self, Cancelled};
+use crate::export::{self, ExportFormat};
+use crate::indicators::{self, Coloring, IndicatorColumn, IndicatorRegistry};
+use crate::klines::FetchFailure;
+use crate::progress::{ProgressEvent, ProgressReporter, RefreshProgress, Stage};
+use crate::snapshots::{self, SnapshotInfo};
+use crate::storage::{self, Storage};
+use crate::storage_utils::{self, Exchange, Market};
+use crate::stream;
 
 // --- Data & App State ---
 
+/// Tallest the fetch error panel grows, borders included.
+const MAX_ERROR_PANEL_HEIGHT: u16 = 8;
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct OutputData {
     pub last_updated_timestamp: i64,
     pub results: Vec<AssetResult>,
+    /// Symbols the last refresh could not download, read from `fetch_errors.json`.
+    #[serde(skip, default)]
+    pub fetch_errors: Vec<FetchFailure>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
+#[serde(from = "StoredAssetResult")]
 pub struct AssetResult {
     pub symbol: String,
-    #[serde(rename = "subType")]
+    pub exchange: Exchange,
+    pub market: Market,
     pub sub_type: Vec<String>,
     pub movement_pct: f64,
-    pub rsi: Option<f64>, // Added RSI field
+    /// Indicator values by column key.
+    pub indicators: BTreeMap<String, f64>,
+}
+
+/// A result as stored, which before indicators were configurable carried
+/// its RSI in a field of its own.
+#[derive(Deserialize)]
+struct StoredAssetResult {
+    symbol: String,
+    #[serde(default)]
+    exchange: Exchange,
+    #[serde(default)]
+    market: Market,
+    #[serde(rename = "subType")]
+    sub_type: Vec<String>,
+    movement_pct: f64,
+    #[serde(default)]
+    indicators: BTreeMap<String, f64>,
+    #[serde(default)]
+    rsi: Option<f64>,
+}
+
+impl From<StoredAssetResult> for AssetResult {
+    fn from(stored: StoredAssetResult) -> Self {
+        let mut indicators = stored.indicators;
+        if let Some(rsi) = stored.rsi {
+            indicators.entry("rsi".to_string()).or_insert(rsi);
+        }
+        Self {
+            symbol: stored.symbol,
+            exchange: stored.exchange,
+            market: stored.market,
+            sub_type: stored.sub_type,
+            movement_pct: stored.movement_pct,
+            indicators,
+        }
+    }
 }
 
 struct App {
     data: OutputData,
     is_refreshing: bool,
+    is_streaming: bool,
+    progress: RefreshProgress,
+    /// Cancels the running refresh or stream.
+    cancel: Option<CancellationToken>,
+    /// Outcome of the last refresh or export worth telling the user about.
+    notice: Option<String>,
+    export_format: ExportFormat,
+    /// Past rankings being browsed, while the history view is open.
+    history: Option<History>,
+    registry: IndicatorRegistry,
+    /// Sidebar entries: the movement view, then one per indicator.
     indicators: Vec<String>,
     selected_indicator_index: usize,
+    /// Value column of the selected view the table is sorted by, highest
+    /// first. Unset keeps the movement ranking.
+    sort_column: Option<usize>,
+    /// Shows the table in the opposite order.
+    sort_reversed: bool,
 }
 
 impl App {
-    async fn new() -> Result<Self> {
+    async fn new(registry: IndicatorRegistry) -> Result<Self> {
+        let mut indicators = vec!["Cumulative Price Change".to_string()];
+        indicators.extend(
+            registry
+                .indicators()
+                .iter()
+                .map(|i| indicators::title(i.as_ref())),
+        );
         let initial_data = load_data().await.unwrap_or_else(|_| OutputData {
             last_updated_timestamp: 0,
             results: Vec::new(),
+            fetch_errors: Vec::new(),
         });
         Ok(Self {
             data: initial_data,
             is_refreshing: false,
-            indicators: vec![
-                "Cumulative Price Change".to_string(),
-                "Relative Strength Index".to_string(),
-            ],
+            is_streaming: false,
+            progress: RefreshProgress::default(),
+            cancel: None,
+            notice: None,
+            export_format: ExportFormat::default(),
+            history: None,
+            registry,
+            indicators,
             selected_indicator_index: 0,
+            sort_column: None,
+            sort_reversed: false,
         })
     }
 
+    /// Value columns of the selected view, none for the movement view, which
+    /// is entry 0 of the sidebar. The others follow the registry.
+    fn value_columns(&self) -> Vec<IndicatorColumn> {
+        match self.selected_indicator_index.checked_sub(1) {
+            Some(i) => self.registry.indicators()[i].columns(),
+            None => Vec::new(),
+        }
+    }
+
+    fn select_indicator(&mut self, index: usize) {
+        if index != self.selected_indicator_index {
+            self.selected_indicator_index = index;
+            self.sort_column = None;
+            self.sort_reversed = false;
+        }
+    }
+
+    /// Moves the sort to the next value column, back to the ranking after
+    /// the last one.
+    fn cycle_sort(&mut self) {
+        let next = self.sort_column.map_or(0, |i| i + 1);
+        self.sort_column = (next < self.value_columns().len()).then_some(next);
+    }
+
+    fn start_refresh(&mut self) -> CancellationToken {
+        self.is_refreshing = true;
+        self.progress = RefreshProgress::default();
+        self.notice = None;
+        let token = CancellationToken::new();
+        self.cancel = Some(token.clone());
+        token
+    }
+
+    fn cancel_refresh(&self) {
+        if let Some(token) = &self.cancel {
+            token.cancel();
+        }
+    }
+
+    fn set_error(&mut self, error: anyhow::Error) {
+        self.notice = Some(if !error.is::<Cancelled>() {
+            format!("Refresh failed: {}", error)
+        } else if self.is_streaming {
+            "Streaming stopped".to_string()
+        } else {
+            "Refresh cancelled".to_string()
+        });
+        self.is_refreshing = false;
+        self.is_streaming = false;
+        self.cancel = None;
+    }
+
     fn set_data(&mut self, new_data: OutputData) {
         self.data = new_data;
         self.is_refreshing = false;
     }
 }
 
+/// One snapshot of the history view, with the ranks of the snapshot before it
+/// to show how symbols moved.
+struct History {
+    /// Every stored snapshot, newest first.
+    snapshots: Vec<SnapshotInfo>,
+    index: usize,
+    data: OutputData,
+    previous_ranks: HashMap<(Exchange, Market, String), usize>,
+}
+
+impl History {
+    async fn load(snapshots: Vec<SnapshotInfo>, index: usize) -> Result<Self> {
+        let storage = open_storage().await?;
+        let data = snapshots::load(storage.as_ref(), snapshots[index]).await?;
+        let previous_ranks = match snapshots.get(index + 1) {
+            Some(&previous) => ranks(&snapshots::load(storage.as_ref(), previous).await?),
+            None => HashMap::new(),
+        };
+        Ok(Self {
+            snapshots,
+            index,
+            data,
+            previous_ranks,
+        })
+    }
+
+    /// How many places `asset` climbed since the previous snapshot, `None`
+    /// when it was not ranked there.
+    fn rank_change(&self, rank: usize, asset: &AssetResult) -> Option<i64> {
+        self.previous_ranks
+            .get(&(asset.exchange, asset.market, asset.symbol.clone()))
+            .map(|&previous| previous as i64 - rank as i64)
+    }
+}
+
+fn ranks(data: &OutputData) -> HashMap<(Exchange, Market, String), usize> {
+    data.results
+        .iter()
+        .enumerate()
+        .map(|(i, r)| ((r.exchange, r.market, r.symbol.clone()), i + 1))
+        .collect()
+}
+
+/// Steps the history view `by` snapshots, towards older ones when positive.
+async fn step_history(app: &mut App, by: isize) {
+    let Some(history) = app.history.take() else {
+        return;
+    };
+    let index = history.index.saturating_add_signed(by);
+    if index == history.index || index >= history.snapshots.len() {
+        app.history = Some(history);
+        return;
+    }
+    match History::load(history.snapshots.clone(), index).await {
+        Ok(moved) => app.history = Some(moved),
+        Err(e) => {
+            app.notice = Some(format!("Could not load snapshot: {}", e));
+            app.history = Some(history);
+        }
+    }
+}
+
+async fn toggle_history(app: &mut App) {
+    if app.history.take().is_some() {
+        return;
+    }
+    let listed = match open_storage().await {
+        Ok(storage) => snapshots::list(storage.as_ref()).await,
+        Err(e) => Err(e),
+    };
+    let loaded = match listed {
+        Ok(list) if list.is_empty() => {
+            app.notice = Some("No snapshots yet".to_string());
+            return;
+        }
+        Ok(list) => History::load(list, 0).await,
+        Err(e) => Err(e),
+    };
+    match loaded {
+        Ok(history) => app.history = Some(history),
+        Err(e) => app.notice = Some(format!("Could not load snapshots: {}", e)),
+    }
+}
+
+/// Senders the background tasks report back to the UI loop on.
+struct Channels {
+    data: mpsc::Sender<Result<OutputData>>,
+    progress: mpsc::UnboundedSender<ProgressEvent>,
+    notices: mpsc::UnboundedSender<String>,
+}
+
 // --- Data Loading ---
 
+async fn open_storage() -> Result<Box<dyn Storage>> {
+    storage::open(&storage_utils::load_config().await?).await
+}
+
 pub async fn load_data() -> Result<OutputData> {
-    let storage = AsyncStorageManager::new_relative("storage").await?;
-    storage.load("results").await
+    let storage = open_storage().await?;
+    let mut data: OutputData = storage.load_results().await?;
+    data.fetch_errors = storage.load_fetch_errors().await.unwrap_or_default();
+    Ok(data)
 }
 
 // --- TUI ---
 
-pub async fn run_tui() -> Result<()> {
+/// Runs the UI until the user quits, starting with `notice` in the status bar.
+pub async fn run_tui(notice: Option<String>) -> Result<()> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let res = run_app(&mut terminal).await;
+    let res = run_app(&mut terminal, notice).await;
 
     disable_raw_mode()?;
     execute!(
@@ -93,29 +326,47 @@ pub async fn run_tui() -> Result<()> {
     res
 }
 
-async fn run_app<B: Backend>(terminal: &mut Terminal<B>) -> Result<()> {
+async fn run_app<B: Backend>(terminal: &mut Terminal<B>, notice: Option<String>) -> Result<()> {
     let (data_tx, mut data_rx) = mpsc::channel::<Result<OutputData>>(1);
-    let mut app = App::new().await?;
+    let (progress_tx, mut progress_rx) = mpsc::unbounded_channel::<ProgressEvent>();
+    let (notice_tx, mut notice_rx) = mpsc::unbounded_channel::<String>();
+    let channels = Channels {
+        data: data_tx,
+        progress: progress_tx,
+        notices: notice_tx,
+    };
+    if let Some(notice) = notice {
+        let _ = channels.notices.send(notice);
+    }
+    let app_config = storage_utils::load_config().await?;
+    let mut app = App::new(app_config.indicator_registry()?).await?;
+    app.export_format = app_config.export_format;
+    if app_config.stream {
+        start_streaming(&mut app, &channels);
+    }
 
     loop {
+        while let Ok(event) = progress_rx.try_recv() {
+            app.progress.apply(event);
+        }
+        while let Ok(notice) = notice_rx.try_recv() {
+            app.notice = Some(notice);
+        }
+
         terminal.draw(|f| ui(f, &app))?;
 
         if let Ok(result) = data_rx.try_recv() {
             match result {
                 Ok(new_data) => app.set_data(new_data),
-                Err(_) => {
-                    app.is_refreshing = false;
-                }
+                Err(e) => app.set_error(e),
             }
         }
 
         if event::poll(Duration::from_millis(50))? {
             match event::read()? {
                 // Handle key presses
-                Event::Key(key) => {
-                    if !handle_key_event(key, &mut app, &data_tx) {
-                        return Err(anyhow!("Quit"));
-                    }
+                Event::Key(key) if !handle_key_event(key, &mut app, &channels).await => {
+                    return Err(anyhow!("Quit"));
                 }
                 // Handle window resizing explicitly
                 Event::Resize(_, _) => {
@@ -128,40 +379,85 @@ async fn run_app<B: Backend>(terminal: &mut Terminal<B>) -> Result<()> {
     }
 }
 
-fn handle_key_event(key: KeyEvent, app: &mut App, tx: &mpsc::Sender<Result<OutputData>>) -> bool {
+/// Seeds history and then follows the kline streams, forwarding every update
+/// of the results to the UI.
+fn start_streaming(app: &mut App, channels: &Channels) {
+    let cancel = app.start_refresh();
+    app.is_streaming = true;
+    let tx_clone = channels.data.clone();
+    let progress = ProgressReporter::new(channels.progress.clone());
+    tokio::spawn(async move {
+        let (update_tx, mut update_rx) = mpsc::channel::<()>(1);
+        let stream_task = tokio::spawn(stream::run(update_tx, progress, cancel));
+
+        while update_rx.recv().await.is_some() {
+            if tx_clone.send(load_data().await).await.is_err() {
+                return;
+            }
+        }
+
+        let result = match stream_task.await {
+            Ok(Ok(())) => return,
+            Ok(Err(e)) => Err(e),
+            Err(e) => Err(e.into()),
+        };
+        let _ = tx_clone.send(result).await;
+    });
+}
+
+async fn handle_key_event(key: KeyEvent, app: &mut App, channels: &Channels) -> bool {
     match key.code {
-        KeyCode::Char('q') => return false,
-        KeyCode::F(5) if !app.is_refreshing => {
-            app.is_refreshing = true;
-            let tx_clone = tx.clone();
+        KeyCode::Char('q') => {
+            app.cancel_refresh();
+            return false;
+        }
+        KeyCode::Esc if app.is_refreshing || app.is_streaming => app.cancel_refresh(),
+        KeyCode::F(5) if !app.is_refreshing && !app.is_streaming => {
+            let cancel = app.start_refresh();
+            let tx_clone = channels.data.clone();
+            let progress = ProgressReporter::new(channels.progress.clone());
             tokio::spawn(async move {
-                let result = match analysis::run_analysis_pipeline().await {
+                let result = match analysis::run_analysis_pipeline(&progress, &cancel).await {
                     Ok(_) => load_data().await,
                     Err(e) => Err(e),
                 };
                 let _ = tx_clone.send(result).await;
             });
         }
-        KeyCode::Up => {
-            if !app.indicators.is_empty() {
-                app.selected_indicator_index = app
-                    .selected_indicator_index
-                    .checked_sub(1)
-                    .unwrap_or(app.indicators.len() - 1);
-            }
+        KeyCode::Char('h') => toggle_history(app).await,
+        KeyCode::Left if app.history.is_some() => step_history(app, 1).await,
+        KeyCode::Right if app.history.is_some() => step_history(app, -1).await,
+        KeyCode::Char('e') => {
+            let format = app.export_format;
+            let notices = channels.notices.clone();
+            tokio::spawn(async move {
+                let notice = match export::export(format, false, None).await {
+                    Ok(paths) => {
+                        let paths: Vec<String> =
+                            paths.iter().map(|p| p.display().to_string()).collect();
+                        format!("Exported to {}", paths.join(", "))
+                    }
+                    Err(e) => format!("Export failed: {}", e),
+                };
+                let _ = notices.send(notice);
+            });
         }
-        KeyCode::Down => {
-            if !app.indicators.is_empty() {
-                app.selected_indicator_index =
-                    (app.selected_indicator_index + 1) % app.indicators.len();
-            }
+        KeyCode::Char('s') => app.cycle_sort(),
+        KeyCode::Char('r') => app.sort_reversed = !app.sort_reversed,
+        KeyCode::Up if !app.indicators.is_empty() => {
+            let index = app
+                .selected_indicator_index
+                .checked_sub(1)
+                .unwrap_or(app.indicators.len() - 1);
+            app.select_indicator(index);
         }
-        KeyCode::Char(c) => {
-            if c.is_ascii_digit() {
-                let digit = c.to_digit(10).unwrap_or(0);
-                if digit > 0 && digit <= app.indicators.len() as u32 {
-                    app.selected_indicator_index = (digit - 1) as usize;
-                }
+        KeyCode::Down if !app.indicators.is_empty() => {
+            app.select_indicator((app.selected_indicator_index + 1) % app.indicators.len());
+        }
+        KeyCode::Char(c) if c.is_ascii_digit() => {
+            let digit = c.to_digit(10).unwrap_or(0);
+            if digit > 0 && digit <= app.indicators.len() as u32 {
+                app.select_indicator((digit - 1) as usize);
             }
         }
         _ => {}
@@ -174,8 +470,18 @@ fn ui(f: &mut Frame, app: &App) {
         .split(f.size());
 
     let left_chunks = Layout::vertical([Constraint::Min(0)]).split(main_layout[1]);
-    let top_chunks =
-        Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).split(left_chunks[0]);
+    // The error panel only takes space when the last refresh had failures.
+    let errors_height = if app.data.fetch_errors.is_empty() {
+        0
+    } else {
+        (app.data.fetch_errors.len() as u16 + 2).min(MAX_ERROR_PANEL_HEIGHT)
+    };
+    let top_chunks = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(0),
+        Constraint::Length(errors_height),
+    ])
+    .split(left_chunks[0]);
 
     // Render the sidebar
     let sidebar_block = Block::default()
@@ -186,7 +492,7 @@ fn ui(f: &mut Frame, app: &App) {
 
     let sidebar_chunks = Layout::vertical([
         Constraint::Min(1),    // For the indicator list
-        Constraint::Length(1), // For the "F5 refreshes data" instruction
+        Constraint::Length(3), // For the refresh, export and sort instructions
     ])
     .split(inner_sidebar_area);
 
@@ -205,106 +511,310 @@ fn ui(f: &mut Frame, app: &App) {
 
     f.render_widget(Paragraph::new(indicator_lines), sidebar_chunks[0]);
 
+    let refresh_hint = if app.is_streaming {
+        "Live: streaming klines"
+    } else {
+        "F5 refreshes data"
+    };
     f.render_widget(
-        Paragraph::new("F5 refreshes data").alignment(Alignment::Center),
+        Paragraph::new(vec![
+            Line::from(refresh_hint),
+            Line::from("E exports, H history"),
+            Line::from("S sorts, R reverses"),
+        ])
+        .alignment(Alignment::Center),
         sidebar_chunks[1],
     );
 
-    let time_str = format_timestamp(app.data.last_updated_timestamp);
+    // The history view replaces the latest results with a past snapshot.
+    let data = app.history.as_ref().map_or(&app.data, |h| &h.data);
+
+    let time_str = format_timestamp(data.last_updated_timestamp);
+    let mut status_title = match &app.history {
+        Some(history) => format!(
+            "Snapshot {}/{}: {} | ←/→ steps, H closes",
+            history.snapshots.len() - history.index,
+            history.snapshots.len(),
+            format_timestamp(history.snapshots[history.index].taken_at)
+        ),
+        None => format!("Last Updated: {}", time_str),
+    };
+    if let Some(notice) = &app.notice {
+        status_title.push_str(&format!(" | {}", notice));
+    }
     f.render_widget(
         Block::default()
             .borders(Borders::ALL)
             .title_alignment(Alignment::Center)
-            .title(format!("Last Updated: {}", time_str)),
+            .title(status_title),
         top_chunks[0],
     );
 
-    let active_indicator_is_rsi = app.selected_indicator_index == 1; // 1 is the index for "Relative Strength Index"
-
-    let header_cells = if active_indicator_is_rsi {
-        vec![
-            Cell::from("Rank"),
-            Cell::from("Asset"),
-            Cell::from("Type"),
-            Cell::from("RSI"),
-        ]
+    let value_columns = app.value_columns();
+    let sort_column = app.sort_column.and_then(|i| value_columns.get(i));
+
+    let mut header_cells = vec![
+        Cell::from("Rank"),
+        Cell::from("Asset"),
+        Cell::from("Market"),
+        Cell::from("Type"),
+    ];
+    if value_columns.is_empty() {
+        header_cells.push(Cell::from("Movement (%)"));
     } else {
-        vec![
-            Cell::from("Rank"),
-            Cell::from("Asset"),
-            Cell::from("Type"),
-            Cell::from("Movement (%)"),
-        ]
-    };
+        header_cells.extend(value_columns.iter().map(|column| {
+            if sort_column == Some(column) {
+                let arrow = if app.sort_reversed { "▲" } else { "▼" };
+                Cell::from(format!("{} {}", column.header, arrow))
+            } else {
+                Cell::from(column.header)
+            }
+        }));
+    }
 
     let header = Row::new(header_cells).style(Style::default().bg(Color::DarkGray));
-    let top_mover_pct = app.data.results.first().map_or(1.0, |r| r.movement_pct);
+    let top_mover_pct = data.results.first().map_or(1.0, |r| r.movement_pct);
     let safe_top_pct = if top_mover_pct == 0.0 {
         1.0
     } else {
         top_mover_pct
     };
 
-    let rows = app
-        .data
-        .results
-        .iter()
-        .take(100)
-        .enumerate()
-        .map(|(i, asset)| {
-            let ratio = get_visibility_ratio(asset.movement_pct, safe_top_pct);
-            let cyan_val = (255.0 * ratio) as u8;
-            let green_val = (255.0 * ratio) as u8;
-            let gray_val = (150.0 * ratio) as u8;
-            let subtype_str = if asset.sub_type.is_empty() {
-                "N/A".to_string()
-            } else {
-                format!("({})", asset.sub_type.join(", "))
-            };
+    // Rows keep their movement rank whichever way they are sorted.
+    let mut ranked: Vec<(usize, &AssetResult)> = data.results.iter().enumerate().collect();
+    if let Some(column) = sort_column {
+        ranked.sort_by(|(_, a), (_, b)| {
+            by_value(
+                a.indicators.get(column.key),
+                b.indicators.get(column.key),
+                app.sort_reversed,
+            )
+        });
+    } else if app.sort_reversed {
+        ranked.reverse();
+    }
 
-            let main_value_cell = if active_indicator_is_rsi {
-                Cell::from(Line::from(format!("{:.2}", asset.rsi.unwrap_or(0.0))))
-                    .style(Style::default().fg(Color::Rgb(0, green_val, 0))) // Use green for RSI as well for consistency
-            } else {
+    let rows = ranked.into_iter().take(100).map(|(i, asset)| {
+        let ratio = get_visibility_ratio(asset.movement_pct, safe_top_pct);
+        let cyan_val = (255.0 * ratio) as u8;
+        let green_val = (255.0 * ratio) as u8;
+        let gray_val = (150.0 * ratio) as u8;
+        let subtype_str = if asset.sub_type.is_empty() {
+            "N/A".to_string()
+        } else {
+            format!("({})", asset.sub_type.join(", "))
+        };
+
+        let gradient = Color::Rgb(0, green_val, 0);
+        let value_cells: Vec<Cell> = if value_columns.is_empty() {
+            vec![
                 Cell::from(Line::from(format!("{:.2}%", asset.movement_pct)))
-                    .style(Style::default().fg(Color::Rgb(0, green_val, 0)))
-            };
-
-            Row::new([
-                Cell::from(format!("{}", i + 1)).style(Style::default().fg(Color::DarkGray)),
-                Cell::from(asset.symbol.clone())
-                    .style(Style::default().fg(Color::Rgb(0, cyan_val, cyan_val))),
-                Cell::from(subtype_str)
-                    .style(Style::default().fg(Color::Rgb(gray_val, gray_val, gray_val))),
-                main_value_cell,
-            ])
-            .height(1)
-        });
+                    .style(Style::default().fg(gradient)),
+            ]
+        } else {
+            value_columns
+                .iter()
+                .map(|column| match asset.indicators.get(column.key) {
+                    Some(&value) => {
+                        Cell::from(Line::from(format!("{:.*}", column.decimals, value))).style(
+                            Style::default().fg(indicator_color(column.coloring, value, gradient)),
+                        )
+                    }
+                    None => Cell::from("-").style(Style::default().fg(Color::DarkGray)),
+                })
+                .collect()
+        };
+
+        let mut rank_spans = vec![Span::styled(
+            format!("{}", i + 1),
+            Style::default().fg(Color::DarkGray),
+        )];
+        if let Some(history) = &app.history {
+            rank_spans.push(match history.rank_change(i + 1, asset) {
+                Some(0) => Span::styled(" =", Style::default().fg(Color::DarkGray)),
+                Some(up) if up > 0 => {
+                    Span::styled(format!(" ▲{}", up), Style::default().fg(Color::Green))
+                }
+                Some(down) => Span::styled(format!(" ▼{}", -down), Style::default().fg(Color::Red)),
+                None => Span::styled(" new", Style::default().fg(Color::Yellow)),
+            });
+        }
+
+        let mut cells = vec![
+            Cell::from(Line::from(rank_spans)),
+            Cell::from(asset.symbol.clone())
+                .style(Style::default().fg(Color::Rgb(0, cyan_val, cyan_val))),
+            Cell::from(format!(
+                "{} {}",
+                asset.exchange.label(),
+                asset.market.label()
+            ))
+            .style(Style::default().fg(Color::Rgb(gray_val, gray_val, gray_val))),
+            Cell::from(subtype_str)
+                .style(Style::default().fg(Color::Rgb(gray_val, gray_val, gray_val))),
+        ];
+        cells.extend(value_cells);
+        Row::new(cells).height(1)
+    });
+    // Rank, and its change in history
+    let rank_width = Constraint::Length(if app.history.is_some() { 10 } else { 6 });
+    let widths = if value_columns.is_empty() {
+        vec![
+            rank_width,
+            Constraint::Percentage(22), // Asset: Takes 22% of width
+            Constraint::Length(15),     // Market: Fits the longest label
+            Constraint::Percentage(33), // Type: Takes 33% (main flexible column)
+            Constraint::Percentage(27), // Movement: Takes 27%
+        ]
+    } else {
+        // Indicator values fit their header and a sort arrow, the type
+        // column gives up its space first.
+        let mut widths = vec![
+            rank_width,
+            Constraint::Length(14), // Asset: Fits most symbols
+            Constraint::Length(15), // Market: Fits the longest label
+            Constraint::Min(4),     // Type
+        ];
+        widths.extend(
+            value_columns
+                .iter()
+                .map(|c| Constraint::Length(c.header.chars().count().max(8) as u16 + 2)),
+        );
+        widths
+    };
     f.render_widget(
-        Table::new(
-            rows,
-            [
-                Constraint::Length(6),      // Rank: Keep fixed small width
-                Constraint::Percentage(27), // Asset: Takes 27% of width
-                Constraint::Percentage(38), // Type: Takes 38% (main flexible column)
-                Constraint::Percentage(35), // Value: Takes 35%
-            ],
-        )
-        .header(header)
-        .block(Block::default().borders(Borders::ALL).title("Top Movers")),
+        Table::new(rows, widths).header(header).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(if app.history.is_some() {
+                    "Top Movers (history)"
+                } else {
+                    "Top Movers"
+                }),
+        ),
         top_chunks[1],
     );
 
+    if !app.data.fetch_errors.is_empty() {
+        let error_lines: Vec<Line> = app
+            .data
+            .fetch_errors
+            .iter()
+            .map(|failure| {
+                Line::from(vec![
+                    Span::styled(
+                        format!("{:<14}", failure.symbol),
+                        Style::default().fg(Color::Red),
+                    ),
+                    Span::styled(
+                        format!(
+                            "{:<16}",
+                            format!("{} {}", failure.exchange.label(), failure.market.label())
+                        ),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::raw(failure.error.to_string()),
+                ])
+            })
+            .collect();
+        f.render_widget(
+            Paragraph::new(error_lines).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Fetch Errors ({})", app.data.fetch_errors.len())),
+            ),
+            top_chunks[2],
+        );
+    }
+
     if app.is_refreshing {
-        let area = centered_rect(60, 20, main_layout[1]);
-        f.render_widget(Clear, area);
+        render_progress(f, &app.progress, centered_rect(60, 30, main_layout[1]));
+    }
+}
+
+/// Shows the refresh stage, symbols downloaded and the ETA. Downloads are
+/// paced continuously by the rate limiter rather than sent in batches, so
+/// there is no batch counter or time to the next batch; a wait is shown only
+/// while the limiter holds requests back.
+fn render_progress(f: &mut Frame, progress: &RefreshProgress, area: Rect) {
+    f.render_widget(Clear, area);
+    let block = Block::default().title("Refreshing").borders(Borders::ALL);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::vertical([
+        Constraint::Length(1), // Stage
+        Constraint::Length(1), // Gauge
+        Constraint::Min(0),    // Details
+    ])
+    .split(inner);
+
+    f.render_widget(
+        Paragraph::new(format!("{}...", progress.stage.label())).alignment(Alignment::Center),
+        chunks[0],
+    );
+
+    if progress.stage != Stage::ExchangeInfo {
         f.render_widget(
-            Paragraph::new("Running analysis pipeline...\nPlease wait.")
-                .block(Block::default().title("Refreshing").borders(Borders::ALL))
-                .alignment(Alignment::Center),
-            area,
+            Gauge::default()
+                .gauge_style(Style::default().fg(Color::Green).bg(Color::DarkGray))
+                .ratio(progress.ratio())
+                .label(format!("{}/{} symbols", progress.fetched, progress.total)),
+            chunks[1],
+        );
+    }
+
+    let mut details = Vec::new();
+    if let Some(eta) = progress.eta() {
+        details.push(Line::from(format!("ETA: {}", format_duration(eta))));
+    }
+    if let Some(wait) = progress.rate_limit_wait() {
+        details.push(
+            Line::from(format!(
+                "Rate limited, resuming in {}",
+                format_duration(wait)
+            ))
+            .style(Style::default().fg(Color::Yellow)),
+        );
+    }
+    if progress.errors > 0 {
+        details.push(
+            Line::from(format!("Errors: {}", progress.errors))
+                .style(Style::default().fg(Color::Red)),
         );
     }
+    details.push(Line::from("Esc cancels").style(Style::default().fg(Color::DarkGray)));
+    f.render_widget(
+        Paragraph::new(details).alignment(Alignment::Center),
+        chunks[2],
+    );
+}
+
+/// Orders values highest first, or lowest first when `reversed`, with
+/// missing values last either way.
+fn by_value(a: Option<&f64>, b: Option<&f64>, reversed: bool) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let order = b.partial_cmp(a).unwrap_or(Ordering::Equal);
+            if reversed { order.reverse() } else { order }
+        }
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Color of an indicator value, `plain` when its coloring does not single it out.
+fn indicator_color(coloring: Coloring, value: f64, plain: Color) -> Color {
+    match coloring {
+        Coloring::Bands { high, .. } if value >= high => Color::Red,
+        Coloring::Bands { low, .. } if value <= low => Color::Green,
+        Coloring::Sign if value > 0.0 => Color::Green,
+        Coloring::Sign if value < 0.0 => Color::Red,
+        Coloring::Highlight { above } if value >= above => Color::Yellow,
+        _ => plain,
+    }
 }
 
 fn get_visibility_ratio(current_pct: f64, top_pct: f64) -> f64 {
@@ -315,7 +825,7 @@ fn get_visibility_ratio(current_pct: f64, top_pct: f64) -> f64 {
     }
 }
 
-fn format_timestamp(ts_ms: i64) -> String {
+pub fn format_timestamp(ts_ms: i64) -> String {
     if ts_ms == 0 {
         return "Never".to_string();
     }
@@ -326,6 +836,15 @@ fn format_timestamp(ts_ms: i64) -> String {
         .unwrap_or_else(|| "Unknown Time".to_string())
 }
 
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::vertical([
         Constraint::Percentage((100 - percent_y) / 2),